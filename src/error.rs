@@ -0,0 +1,99 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum RejectionReason {
+    #[display(fmt = "no queue family supports graphics")]
+    NoGraphicsQueue,
+    #[display(fmt = "no graphics queue family can present to the target windows")]
+    NoSurfaceSupport,
+    #[display(fmt = "rejected by the device selector")]
+    NotSelected,
+}
+
+#[derive(Debug, Clone)]
+pub struct RejectedDevice {
+    pub device_name: String,
+    pub device_id: u32,
+    pub reason: RejectionReason,
+}
+
+impl Display for RejectedDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (id {}): {}",
+            self.device_name, self.device_id, self.reason
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RendererError {
+    /// The vulkan loader library cannot be found or loaded.
+    Loader(String),
+    /// Creating the vulkan instance failed, usually a missing layer or extension.
+    Instance(yarvk::Result),
+    /// The device asked by `RenderDeviceBuilder::device_id` does not exist.
+    DeviceNotFound { device_id: u32 },
+    /// Every physical device was rejected, each one with the reason.
+    NoSuitableDevice { rejected: Vec<RejectedDevice> },
+    /// A requested feature is not supported by the chosen device, or the builder settings
+    /// cannot be combined.
+    UnsupportedFeature(String),
+    /// The chosen device does not expose the queues the renderer needs.
+    InsufficientQueues(String),
+    /// The device cannot present to the window, e.g. it is headless or the window was not
+    /// passed to `RenderDeviceBuilder::target_windows` and is on another adapter. Also returned
+    /// by `RenderDeviceBuilder::build` if the loader offers no surface extension of the platform.
+    UnsupportedSurface,
+    /// The device was lost, e.g. after a driver reset. Recover it with `RenderDevice::recover`.
+    DeviceLost,
+    /// Reading or writing a file failed.
+    Io(String),
+    /// Any other vulkan call failed.
+    Vulkan(yarvk::Result),
+}
+
+impl Display for RendererError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendererError::Loader(message) => write!(f, "failed to load vulkan: {message}"),
+            RendererError::Instance(result) => {
+                write!(f, "failed to create vulkan instance: {result:?}")
+            }
+            RendererError::DeviceNotFound { device_id } => {
+                write!(f, "no device id {device_id} found")
+            }
+            RendererError::NoSuitableDevice { rejected } => {
+                write!(f, "no available device")?;
+                for device in rejected {
+                    write!(f, "\n  {device}")?;
+                }
+                Ok(())
+            }
+            RendererError::UnsupportedFeature(message) => {
+                write!(f, "unsupported feature: {message}")
+            }
+            RendererError::InsufficientQueues(message) => {
+                write!(f, "insufficient queues: {message}")
+            }
+            RendererError::UnsupportedSurface => {
+                write!(f, "the device cannot present to the window")
+            }
+            RendererError::DeviceLost => write!(f, "device lost"),
+            RendererError::Io(message) => write!(f, "io error: {message}"),
+            RendererError::Vulkan(result) => write!(f, "vulkan error: {result:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<yarvk::Result> for RendererError {
+    fn from(result: yarvk::Result) -> Self {
+        match result {
+            yarvk::Result::ERROR_DEVICE_LOST => RendererError::DeviceLost,
+            result => RendererError::Vulkan(result),
+        }
+    }
+}
@@ -10,12 +10,15 @@ use dashmap::DashMap;
 use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use rustc_hash::FxHasher;
 
+pub use error::RendererError;
 pub use rendering_function::forward_rendering::ForwardRenderingFunction;
 
+pub mod error;
 mod pipeline;
 pub mod render_device;
 pub mod render_objects;
 pub mod render_scene;
+pub mod render_target;
 pub mod render_window;
 mod rendering_function;
 pub mod resource;
@@ -1,6 +1,8 @@
 use std::io::Cursor;
+use std::mem::size_of;
 use std::sync::Arc;
 
+use glam::{Mat4, Vec4};
 use tyleri_api::data_structure::vertices::{IVertex, Vertex};
 use tyleri_gpu_utils::descriptor::single_image_descriptor_set_layout::SingleImageDescriptorLayout;
 use yarvk::pipeline::color_blend_state::{
@@ -13,15 +15,29 @@ use yarvk::pipeline::input_assembly_state::{
 use yarvk::pipeline::multisample_state::PipelineMultisampleStateCreateInfo;
 use yarvk::pipeline::rasterization_state::{PipelineRasterizationStateCreateInfo, PolygonMode};
 use yarvk::pipeline::shader_stage::{PipelineShaderStageCreateInfo, ShaderStage};
+use yarvk::pipeline::vertex_input_state::PipelineVertexInputStateCreateInfo;
 use yarvk::pipeline::{Pipeline, PipelineCacheType, PipelineLayout, PushConstantRange};
 use yarvk::render_pass::RenderPass;
 use yarvk::shader_module::ShaderModule;
 use yarvk::StencilOpState;
 use yarvk::{
-    read_spv, BlendOp, ColorComponentFlags, CompareOp, FrontFace, SampleCountFlags, StencilOp,
-    VertexInputRate,
+    read_spv, BlendOp, ColorComponentFlags, CompareOp, Format, FrontFace, SampleCountFlags,
+    StencilOp, VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate,
 };
 
+use crate::pipeline::{is_linear_target, specialization};
+use crate::render_objects::instanced_mesh_renderer::InstanceData;
+use crate::render_objects::mesh_renderer::{BlendMode, MeshInstance, StencilState};
+
+/// Per-instance vertex input of a mesh pipeline, selects its shaders.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum InstanceInput {
+    /// `MeshInstance` of the batched `MeshRenderer`s.
+    Model,
+    /// `InstanceData` of an `InstancedMeshRenderer`.
+    InstanceData,
+}
+
 pub struct CommonPipeline {
     pub pipeline: Arc<Pipeline>,
 }
@@ -33,12 +49,25 @@ impl CommonPipeline {
         pipeline_cache: PipelineCacheType,
         render_pass: &Arc<RenderPass>,
         subpass: u32,
+        color_format: Format,
+        sample_count: SampleCountFlags,
+        stencil: Option<StencilState>,
+        blend_mode: BlendMode,
+        instance_input: InstanceInput,
     ) -> Self {
         let device = &render_pass.device;
-        let mut vertex_spv_file =
-            Cursor::new(&include_bytes!(concat!(env!("OUT_DIR"), "/common_pipeline.vert"))[..]);
-        let mut frag_spv_file =
-            Cursor::new(&include_bytes!(concat!(env!("OUT_DIR"), "/common_pipeline.frag"))[..]);
+        let (vertex_spv, frag_spv) = match instance_input {
+            InstanceInput::Model => (
+                &include_bytes!(concat!(env!("OUT_DIR"), "/common_pipeline.vert"))[..],
+                &include_bytes!(concat!(env!("OUT_DIR"), "/common_pipeline.frag"))[..],
+            ),
+            InstanceInput::InstanceData => (
+                &include_bytes!(concat!(env!("OUT_DIR"), "/instanced_mesh.vert"))[..],
+                &include_bytes!(concat!(env!("OUT_DIR"), "/instanced_mesh.frag"))[..],
+            ),
+        };
+        let mut vertex_spv_file = Cursor::new(vertex_spv);
+        let mut frag_spv_file = Cursor::new(frag_spv);
 
         let vertex_code =
             read_spv(&mut vertex_spv_file).expect("Failed to read vertex shader spv file");
@@ -64,7 +93,7 @@ impl CommonPipeline {
             .build()
             .unwrap();
 
-        let vertex_input_state_info = Vertex::vertex_input_state(VertexInputRate::VERTEX);
+        let vertex_input_state_info = Self::vertex_input_state(instance_input);
         let noop_stencil_state = StencilOpState {
             fail_op: StencilOp::KEEP,
             pass_op: StencilOp::KEEP,
@@ -73,6 +102,15 @@ impl CommonPipeline {
             ..Default::default()
         };
 
+        let stencil_state = stencil
+            .map(|stencil| stencil.op_state())
+            .unwrap_or(noop_stencil_state);
+
+        let (alpha_test, alpha_cutoff) = match blend_mode {
+            BlendMode::AlphaTest { cutoff } => (true, cutoff),
+            _ => (false, 0.0),
+        };
+
         let entry_name = unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0") };
         // let op_feature = device.get_feature::<{ FeatureType::DeviceFeatures(PhysicalDeviceFeatures::LogicOp) }>().unwrap();
         let graphic_pipeline = Pipeline::builder(pipeline_layout)
@@ -84,6 +122,11 @@ impl CommonPipeline {
             .add_stage(
                 PipelineShaderStageCreateInfo::builder(fragment_shader_module, entry_name)
                     .stage(ShaderStage::Fragment)
+                    .specialization_info(specialization(&[
+                        is_linear_target(color_format) as u32,
+                        alpha_test as u32,
+                        alpha_cutoff.to_bits(),
+                    ]))
                     .build(),
             )
             .vertex_input_state(vertex_input_state_info)
@@ -101,32 +144,29 @@ impl CommonPipeline {
             )
             .multisample_state(
                 PipelineMultisampleStateCreateInfo::builder()
-                    .rasterization_samples(SampleCountFlags::TYPE_1)
-                    .build(),
-            )
-            .depth_stencil_state(
-                PipelineDepthStencilStateCreateInfo::builder()
-                    .depth_test_enable()
-                    .depth_write_enable()
-                    .depth_compare_op(CompareOp::LESS_OR_EQUAL)
-                    .front(noop_stencil_state.clone())
-                    .back(noop_stencil_state.clone())
-                    .depth_bounds(0.0, 1.0)
+                    .rasterization_samples(sample_count)
                     .build(),
             )
+            .depth_stencil_state({
+                let mut depth_stencil_state_builder =
+                    PipelineDepthStencilStateCreateInfo::builder()
+                        .depth_test_enable()
+                        .depth_compare_op(CompareOp::LESS_OR_EQUAL)
+                        .front(stencil_state.clone())
+                        .back(stencil_state.clone())
+                        .depth_bounds(0.0, 1.0);
+                // blended meshes are sorted back to front and must not hide each other
+                if !blend_mode.is_blended() {
+                    depth_stencil_state_builder = depth_stencil_state_builder.depth_write_enable();
+                }
+                if stencil.is_some() {
+                    depth_stencil_state_builder = depth_stencil_state_builder.stencil_test_enable();
+                }
+                depth_stencil_state_builder.build()
+            })
             .color_blend_state(
                 PipelineColorBlendStateCreateInfo::builder()
-                    .add_attachment(
-                        PipelineColorBlendAttachmentState::builder()
-                            .src_color_blend_factor(BlendFactor::SrcColor)
-                            .dst_color_blend_factor(BlendFactor::OneMinusDstColor)
-                            .color_blend_op(BlendOp::ADD)
-                            .src_alpha_blend_factor(BlendFactor::Zero)
-                            .dst_alpha_blend_factor(BlendFactor::Zero)
-                            .alpha_blend_op(BlendOp::ADD)
-                            .color_write_mask(ColorComponentFlags::RGBA)
-                            .build(),
-                    )
+                    .add_attachment(Self::color_blend_attachment(blend_mode))
                     .build(),
             )
             .cache(pipeline_cache)
@@ -137,4 +177,93 @@ impl CommonPipeline {
             pipeline: graphic_pipeline,
         }
     }
+    /// Binding 0 holds the mesh vertices as described by `Vertex`, binding 1 the instances.
+    fn vertex_input_state(instance_input: InstanceInput) -> PipelineVertexInputStateCreateInfo {
+        let instance_stride = match instance_input {
+            InstanceInput::Model => size_of::<MeshInstance>(),
+            InstanceInput::InstanceData => size_of::<InstanceData>(),
+        };
+        let mut vertex_input_state = Vertex::vertex_input_state(VertexInputRate::VERTEX);
+        vertex_input_state
+            .vertex_binding_descriptions
+            .push(VertexInputBindingDescription {
+                binding: 1,
+                stride: instance_stride as _,
+                input_rate: VertexInputRate::INSTANCE,
+            });
+        // one location per column of the model matrix, following the locations of `Vertex`;
+        // the instance structs are `#[repr(C)]` with the model first
+        for column in 0..4 {
+            vertex_input_state.vertex_attribute_descriptions.push(
+                VertexInputAttributeDescription {
+                    location: 2 + column,
+                    binding: 1,
+                    format: Format::R32G32B32A32_SFLOAT,
+                    offset: (column as usize * size_of::<Vec4>()) as _,
+                },
+            );
+        }
+        if instance_input == InstanceInput::InstanceData {
+            vertex_input_state.vertex_attribute_descriptions.extend([
+                VertexInputAttributeDescription {
+                    location: 6,
+                    binding: 1,
+                    format: Format::R32G32B32A32_SFLOAT,
+                    offset: size_of::<Mat4>() as _,
+                },
+                VertexInputAttributeDescription {
+                    location: 7,
+                    binding: 1,
+                    format: Format::R32G32_SFLOAT,
+                    offset: (size_of::<Mat4>() + size_of::<Vec4>()) as _,
+                },
+            ]);
+        }
+        vertex_input_state
+    }
+    fn color_blend_attachment(blend_mode: BlendMode) -> PipelineColorBlendAttachmentState {
+        // (src color, dst color, src alpha, dst alpha) factors
+        let factors = match blend_mode {
+            BlendMode::Opaque | BlendMode::AlphaTest { .. } => {
+                return PipelineColorBlendAttachmentState::builder()
+                    .src_color_blend_factor(BlendFactor::SrcColor)
+                    .dst_color_blend_factor(BlendFactor::OneMinusDstColor)
+                    .color_blend_op(BlendOp::ADD)
+                    .src_alpha_blend_factor(BlendFactor::Zero)
+                    .dst_alpha_blend_factor(BlendFactor::Zero)
+                    .alpha_blend_op(BlendOp::ADD)
+                    .color_write_mask(ColorComponentFlags::RGBA)
+                    .build();
+            }
+            BlendMode::AlphaBlend => (
+                BlendFactor::SrcAlpha,
+                BlendFactor::OneMinusSrcAlpha,
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+            ),
+            BlendMode::Additive => (
+                BlendFactor::SrcAlpha,
+                BlendFactor::One,
+                BlendFactor::Zero,
+                BlendFactor::One,
+            ),
+            BlendMode::Premultiplied => (
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+            ),
+        };
+        let (src_color, dst_color, src_alpha, dst_alpha) = factors;
+        PipelineColorBlendAttachmentState::builder()
+            .blend_enable()
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(BlendOp::ADD)
+            .color_write_mask(ColorComponentFlags::RGBA)
+            .build()
+    }
 }
@@ -4,16 +4,18 @@
 
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 uv;
+// per instance, a matrix takes one location per column
+layout (location = 2) in mat4 model;
 
 layout( push_constant ) uniform constants
 {
-	mat4 view_x_model;
+	mat4 view;
 	mat4 projection;
-} MVP;
+} VP;
 
 
 layout (location = 0) out vec2 o_uv;
 void main() {
     o_uv = uv;
-    gl_Position = MVP.projection * MVP.view_x_model * vec4(pos, 1.0);
+    gl_Position = VP.projection * VP.view * model * vec4(pos, 1.0);
 }
@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+// the attachment takes linear colors, textures and instance colors are sRGB encoded
+layout (constant_id = 0) const bool LINEAR_TARGET = false;
+layout (constant_id = 1) const bool ALPHA_TEST = false;
+layout (constant_id = 2) const float ALPHA_CUTOFF = 0.5;
+
+layout (binding = 0) uniform sampler2D samplerColor;
+
+layout (location = 0) in vec2 o_uv;
+layout (location = 1) in vec4 o_color;
+layout (location = 0) out vec4 uFragColor;
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+void main() {
+    vec4 color = texture(samplerColor, o_uv) * o_color;
+    if (ALPHA_TEST && color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+    if (LINEAR_TARGET) {
+        color.rgb = srgb_to_linear(color.rgb);
+    }
+    uFragColor = color;
+}
@@ -0,0 +1,25 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+layout (location = 0) in vec3 pos;
+layout (location = 1) in vec2 uv;
+// per instance, a matrix takes one location per column
+layout (location = 2) in mat4 model;
+layout (location = 6) in vec4 color;
+layout (location = 7) in vec2 uv_offset;
+
+layout( push_constant ) uniform constants
+{
+	mat4 view;
+	mat4 projection;
+} VP;
+
+
+layout (location = 0) out vec2 o_uv;
+layout (location = 1) out vec4 o_color;
+void main() {
+    o_uv = uv + uv_offset;
+    o_color = color;
+    gl_Position = VP.projection * VP.view * model * vec4(pos, 1.0);
+}
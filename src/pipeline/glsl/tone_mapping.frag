@@ -0,0 +1,62 @@
+#version 450
+
+// the attachment takes linear colors, only used by the SDR path
+layout(constant_id = 0) const bool LINEAR_TARGET = false;
+// 0: ACES filmic to SDR, 1: HDR10 PQ, 2: scRGB linear
+layout(constant_id = 1) const int TONE_MAPPING = 0;
+
+layout(location = 0) in vec2 inUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0, set = 0) uniform sampler2D hdr_color;
+
+layout(push_constant) uniform PushConstants {
+  float exposure;
+  float paper_white_nits;
+  float max_luminance_nits;
+}
+params;
+
+// column major
+const mat3 REC709_TO_REC2020 =
+    mat3(0.6274, 0.0691, 0.0164, 0.3293, 0.9195, 0.0880, 0.0433, 0.0114, 0.8956);
+
+// Narkowicz 2015, ACES filmic curve fit
+vec3 aces_filmic(vec3 x) {
+  return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 linear_to_srgb(vec3 color) {
+  vec3 low = color * 12.92;
+  vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+  return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 pq_encode(vec3 nits) {
+  const float m1 = 0.1593017578125;
+  const float m2 = 78.84375;
+  const float c1 = 0.8359375;
+  const float c2 = 18.8515625;
+  const float c3 = 18.6875;
+  vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+  return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+  vec3 color = max(texture(hdr_color, inUV).rgb * params.exposure, vec3(0.0));
+  if (TONE_MAPPING == 1) {
+    vec3 nits = REC709_TO_REC2020 * color * params.paper_white_nits;
+    color = pq_encode(min(nits, vec3(params.max_luminance_nits)));
+  } else if (TONE_MAPPING == 2) {
+    vec3 nits = color * params.paper_white_nits;
+    // scRGB 1.0 is 80 nits
+    color = min(nits, vec3(params.max_luminance_nits)) / 80.0;
+  } else {
+    color = aces_filmic(color);
+    if (!LINEAR_TARGET) {
+      color = linear_to_srgb(color);
+    }
+  }
+  outColor = vec4(color, 1.0);
+}
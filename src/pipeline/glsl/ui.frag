@@ -1,5 +1,8 @@
 #version 450
 
+// the attachment takes linear colors, UI colors and textures are sRGB encoded
+layout(constant_id = 0) const bool LINEAR_TARGET = false;
+
 layout(location = 0) in vec4 inColor;
 layout(location = 1) in vec2 inUV;
 
@@ -7,4 +10,15 @@ layout(location = 0) out vec4 outColor;
 
 layout(binding = 0, set = 0) uniform sampler2D font_texture;
 
-void main() { outColor = inColor * texture(font_texture, inUV); }
+vec3 srgb_to_linear(vec3 color) {
+  vec3 low = color / 12.92;
+  vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+  return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+void main() {
+  outColor = inColor * texture(font_texture, inUV);
+  if (LINEAR_TARGET) {
+    outColor.rgb = srgb_to_linear(outColor.rgb);
+  }
+}
@@ -1,2 +1,50 @@
+use yarvk::pipeline::shader_stage::{SpecializationInfo, SpecializationMapEntry};
+use yarvk::Format;
+
 pub mod common_pipeline;
+pub mod tone_mapping_pipeline;
 pub mod ui_pipeline;
+
+pub(crate) fn is_srgb_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::R8G8B8A8_SRGB
+            | Format::B8G8R8A8_SRGB
+            | Format::A8B8G8R8_SRGB_PACK32
+            | Format::R8G8B8_SRGB
+            | Format::B8G8R8_SRGB
+    )
+}
+
+fn is_float_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::R16G16B16A16_SFLOAT | Format::R32G32B32A32_SFLOAT | Format::B10G11R11_UFLOAT_PACK32
+    )
+}
+
+/// Specialization constants with `constant_id` equal to their index.
+pub(crate) fn specialization(constants: &[u32]) -> SpecializationInfo {
+    let mut builder = SpecializationInfo::builder();
+    let mut data = Vec::with_capacity(constants.len() * std::mem::size_of::<u32>());
+    for (index, constant) in constants.iter().enumerate() {
+        builder = builder.add_map_entry(SpecializationMapEntry {
+            constant_id: index as u32,
+            offset: data.len() as u32,
+            size: std::mem::size_of::<u32>(),
+        });
+        data.extend_from_slice(&constant.to_ne_bytes());
+    }
+    builder.data(&data).build()
+}
+
+/// sRGB and floating-point attachments take linear colors, UNORM attachments take sRGB
+/// encoded colors.
+pub(crate) fn is_linear_target(color_format: Format) -> bool {
+    is_srgb_format(color_format) || is_float_format(color_format)
+}
+
+/// Sets the fragment shaders' LINEAR_TARGET, see `is_linear_target`.
+pub(crate) fn color_format_specialization(color_format: Format) -> SpecializationInfo {
+    specialization(&[is_linear_target(color_format) as u32])
+}
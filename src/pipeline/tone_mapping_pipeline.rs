@@ -0,0 +1,123 @@
+use std::io::Cursor;
+use std::sync::Arc;
+
+use tyleri_gpu_utils::descriptor::single_image_descriptor_set_layout::SingleImageDescriptorLayout;
+use yarvk::pipeline::color_blend_state::{
+    PipelineColorBlendAttachmentState, PipelineColorBlendStateCreateInfo,
+};
+use yarvk::pipeline::input_assembly_state::{
+    PipelineInputAssemblyStateCreateInfo, PrimitiveTopology,
+};
+use yarvk::pipeline::multisample_state::PipelineMultisampleStateCreateInfo;
+use yarvk::pipeline::rasterization_state::{PipelineRasterizationStateCreateInfo, PolygonMode};
+use yarvk::pipeline::shader_stage::{PipelineShaderStageCreateInfo, ShaderStage};
+use yarvk::pipeline::vertex_input_state::PipelineVertexInputStateCreateInfo;
+use yarvk::pipeline::{Pipeline, PipelineCacheType, PipelineLayout, PushConstantRange};
+use yarvk::render_pass::RenderPass;
+use yarvk::shader_module::ShaderModule;
+use yarvk::{read_spv, ColorComponentFlags, Format, FrontFace, SampleCountFlags};
+
+use crate::pipeline::{is_srgb_format, specialization};
+use crate::render_target::hdr::ToneMapping;
+
+#[repr(C)]
+pub(crate) struct ToneMappingParams {
+    pub(crate) exposure: f32,
+    pub(crate) paper_white_nits: f32,
+    pub(crate) max_luminance_nits: f32,
+}
+
+pub struct ToneMappingPipeline {
+    pub pipeline: Arc<Pipeline>,
+}
+
+impl ToneMappingPipeline {
+    pub fn new(
+        single_image_descriptor_layout: &SingleImageDescriptorLayout,
+        pipeline_cache: PipelineCacheType,
+        render_pass: &Arc<RenderPass>,
+        subpass: u32,
+        color_format: Format,
+        tone_mapping: ToneMapping,
+    ) -> Self {
+        let device = &render_pass.device;
+        let mut vertex_spv_file =
+            Cursor::new(&include_bytes!(concat!(env!("OUT_DIR"), "/tone_mapping.vert"))[..]);
+        let mut frag_spv_file =
+            Cursor::new(&include_bytes!(concat!(env!("OUT_DIR"), "/tone_mapping.frag"))[..]);
+
+        let vertex_code =
+            read_spv(&mut vertex_spv_file).expect("Failed to read vertex shader spv file");
+
+        let frag_code =
+            read_spv(&mut frag_spv_file).expect("Failed to read fragment shader spv file");
+
+        let vertex_shader_module = ShaderModule::builder(&device, &vertex_code)
+            .build()
+            .unwrap();
+
+        let fragment_shader_module = ShaderModule::builder(&device, &frag_code).build().unwrap();
+
+        let pipeline_layout = PipelineLayout::builder(&device)
+            .add_set_layout(single_image_descriptor_layout.desc_set_layout.clone())
+            .add_push_constant_range(
+                PushConstantRange::builder()
+                    .add_stage(ShaderStage::Fragment)
+                    .offset(0)
+                    .size(std::mem::size_of::<ToneMappingParams>() as _)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+
+        let entry_name = unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(b"main\0") };
+        let pipeline = Pipeline::builder(pipeline_layout)
+            .add_stage(
+                PipelineShaderStageCreateInfo::builder(vertex_shader_module, entry_name)
+                    .stage(ShaderStage::Vertex)
+                    .build(),
+            )
+            .add_stage(
+                PipelineShaderStageCreateInfo::builder(fragment_shader_module, entry_name)
+                    .stage(ShaderStage::Fragment)
+                    .specialization_info(specialization(&[
+                        is_srgb_format(color_format) as u32,
+                        tone_mapping as u32,
+                    ]))
+                    .build(),
+            )
+            // the full screen triangle is generated from vertex indices
+            .vertex_input_state(PipelineVertexInputStateCreateInfo::builder().build())
+            .input_assembly_state(
+                PipelineInputAssemblyStateCreateInfo::builder()
+                    .topology::<{ PrimitiveTopology::TriangleList }>()
+                    .build(),
+            )
+            .rasterization_state(
+                PipelineRasterizationStateCreateInfo::builder()
+                    .front_face(FrontFace::COUNTER_CLOCKWISE)
+                    .line_width(1.0)
+                    .polygon_mode(PolygonMode::Fill)
+                    .build(),
+            )
+            .multisample_state(
+                PipelineMultisampleStateCreateInfo::builder()
+                    .rasterization_samples(SampleCountFlags::TYPE_1)
+                    .build(),
+            )
+            .color_blend_state(
+                PipelineColorBlendStateCreateInfo::builder()
+                    .add_attachment(
+                        PipelineColorBlendAttachmentState::builder()
+                            .color_write_mask(ColorComponentFlags::RGBA)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .cache(pipeline_cache)
+            .render_pass(render_pass.clone(), subpass)
+            .build()
+            .unwrap();
+        Self { pipeline }
+    }
+}
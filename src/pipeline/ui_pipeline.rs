@@ -17,10 +17,12 @@ use yarvk::pipeline::{Pipeline, PipelineCacheType, PipelineLayout, PushConstantR
 use yarvk::render_pass::RenderPass;
 use yarvk::shader_module::ShaderModule;
 use yarvk::{
-    read_spv, ColorComponentFlags, CompareOp, FrontFace, SampleCountFlags, StencilOp,
+    read_spv, ColorComponentFlags, CompareOp, Format, FrontFace, SampleCountFlags, StencilOp,
     StencilOpState, VertexInputRate,
 };
 
+use crate::pipeline::color_format_specialization;
+
 pub struct UIPipeline {
     pub pipeline: Arc<Pipeline>,
 }
@@ -31,6 +33,8 @@ impl UIPipeline {
         pipeline_cache: PipelineCacheType,
         render_pass: &Arc<RenderPass>,
         subpass: u32,
+        color_format: Format,
+        sample_count: SampleCountFlags,
     ) -> UIPipeline {
         let device = &render_pass.device;
         let mut vertex_spv_file =
@@ -82,6 +86,7 @@ impl UIPipeline {
             .add_stage(
                 PipelineShaderStageCreateInfo::builder(fragment_shader_module, entry_name)
                     .stage(ShaderStage::Fragment)
+                    .specialization_info(color_format_specialization(color_format))
                     .build(),
             )
             .vertex_input_state(vertex_input_state_info)
@@ -99,7 +104,7 @@ impl UIPipeline {
             )
             .multisample_state(
                 PipelineMultisampleStateCreateInfo::builder()
-                    .rasterization_samples(SampleCountFlags::TYPE_1)
+                    .rasterization_samples(sample_count)
                     .build(),
             )
             .depth_stencil_state(
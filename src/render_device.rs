@@ -1,6 +1,9 @@
 pub mod builders;
+pub mod device_selector;
+mod pipeline_cache;
+pub mod validation;
 
-use crossbeam_queue::SegQueue;
+use parking_lot::Mutex;
 use std::sync::Arc;
 use tyleri_gpu_utils::descriptor::single_image_descriptor_set_layout::SingleImageDescriptorLayout;
 
@@ -8,18 +11,59 @@ use tyleri_gpu_utils::queue::parallel_recording_queue::ParallelRecordingQueue;
 use yarvk::device::Device;
 use yarvk::physical_device::queue_family_properties::QueueFamilyProperties;
 use yarvk::pipeline::pipeline_cache::PipelineCacheImpl;
-use yarvk::Format;
+use yarvk::{Format, ImageAspectFlags, SampleCountFlags};
 
+use crate::render_device::builders::RenderDeviceBuilder;
+use crate::render_device::validation::ValidationErrors;
 use crate::resource::resource_allocator::MemoryAllocator;
+use crate::RendererError;
+
+/// A queue locked for every submission, the same queue may be shared for presentation and
+/// uploads on devices with a single queue.
+pub(crate) type SharedQueue = Arc<Mutex<ParallelRecordingQueue>>;
 
 pub struct RenderDevice {
     pub(crate) device: Arc<Device>,
     pub(crate) single_image_descriptor_set_layout: SingleImageDescriptorLayout,
     pub(crate) present_queue_family: QueueFamilyProperties,
-    pub(crate) present_queues: SegQueue<ParallelRecordingQueue>,
+    pub(crate) present_queue: SharedQueue,
     pub(crate) memory_allocator: MemoryAllocator,
     pub(crate) pipeline_cache: PipelineCacheImpl<false>,
     pub(crate) depth_image_format: Format,
+    pub(crate) stencil_clear_value: u32,
+    pub(crate) msaa_sample_count: SampleCountFlags,
+    /// Counted if `RenderDeviceBuilder::panic_on_validation_error` is set.
+    validation_errors: Option<Arc<ValidationErrors>>,
+    builder: Arc<RenderDeviceBuilder>,
 }
 
-impl RenderDevice {}
+impl RenderDevice {
+    /// Builds a new device with the settings of this one, after `RenderWindow::render`
+    /// reported `RendererError::DeviceLost`. Windows have to be recovered with the new device,
+    /// resources are uploaded again by `RenderDeviceBuilder::on_device_recovered`.
+    pub fn recover(&self) -> Result<RenderDevice, RendererError> {
+        let render_device = self.builder.create_render_device()?;
+        self.builder.device_recovered(&render_device);
+        Ok(render_device)
+    }
+    /// Panics if the validation layer reported errors since the last check and
+    /// `RenderDeviceBuilder::panic_on_validation_error` is set.
+    pub(crate) fn check_validation_errors(&self) {
+        if let Some(validation_errors) = &self.validation_errors {
+            validation_errors.check();
+        }
+    }
+    pub(crate) fn depth_has_stencil(&self) -> bool {
+        matches!(
+            self.depth_image_format,
+            Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT
+        )
+    }
+    pub(crate) fn depth_aspect_mask(&self) -> ImageAspectFlags {
+        if self.depth_has_stencil() {
+            ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL
+        } else {
+            ImageAspectFlags::DEPTH
+        }
+    }
+}
@@ -1,16 +1,21 @@
-use crossbeam_queue::SegQueue;
-use std::collections::BTreeMap;
-use std::ffi::CStr;
+use parking_lot::Mutex;
+use raw_window_handle::{
+    AndroidDisplayHandle, AppKitDisplayHandle, RawDisplayHandle, WaylandDisplayHandle,
+    WindowsDisplayHandle, XcbDisplayHandle, XlibDisplayHandle,
+};
+use rustc_hash::FxHashSet;
+use std::ffi::{c_char, CStr};
+use std::path::Path;
 use std::sync::Arc;
 use tyleri_gpu_utils::descriptor::single_image_descriptor_set_layout::SingleImageDescriptorLayout;
 
 use tyleri_gpu_utils::queue::parallel_recording_queue::ParallelRecordingQueue;
 use yarvk::debug_utils_messenger::DebugUtilsMessengerCreateInfoEXT;
 use yarvk::device::{Device, DeviceBuilder, DeviceQueueCreateInfo};
-use yarvk::device_features::PhysicalDeviceFeatures::{GeometryShader, SamplerAnisotropy};
+use yarvk::device_features::PhysicalDeviceFeatures::SamplerAnisotropy;
 use yarvk::device_features::{DeviceFeatures, PhysicalDeviceFeatures};
 use yarvk::entry::Entry;
-use yarvk::extensions::{DeviceExtensionType, PhysicalInstanceExtensionType};
+use yarvk::extensions::{DeviceExtensionType, InstanceExtension, PhysicalInstanceExtensionType};
 use yarvk::instance::{ApplicationInfo, Instance};
 use yarvk::physical_device::PhysicalDevice;
 use yarvk::pipeline::pipeline_cache::{PipelineCache, PipelineCacheImpl};
@@ -18,29 +23,99 @@ use yarvk::sampler::Sampler;
 use yarvk::surface::Surface;
 use yarvk::window::enumerate_required_extensions;
 use yarvk::{
-    BorderColor, CompareOp, DebugUtilsMessageSeverityFlagsEXT, Filter, Format, PhysicalDeviceType,
-    QueueFlags, SamplerAddressMode, SamplerMipmapMode,
+    BorderColor, CompareOp, DebugUtilsMessageSeverityFlagsEXT, Filter, Format, FormatFeatureFlags,
+    PhysicalDeviceLimits, QueueFlags, SampleCountFlags, SamplerAddressMode, SamplerMipmapMode,
+    API_VERSION_1_1,
 };
 
-use crate::render_device::RenderDevice;
+use crate::error::{RejectedDevice, RejectionReason, RendererError};
+use crate::render_device::device_selector::{
+    device_name, DeviceInfo, DeviceSelector, PreferDiscrete,
+};
+use crate::render_device::pipeline_cache::is_valid_pipeline_cache;
+use crate::render_device::validation::{ValidationErrors, ValidationMessage, ValidationOutput};
+use crate::render_device::{RenderDevice, SharedQueue};
 use crate::resource::resource_allocator::MemoryAllocator;
 use crate::WindowHandle;
 
 const DEFAULT_APP_NAME: &str = "Tyleri App";
 const DEFAULT_ENGINE_NAME: &str = "Tyleri Engine";
 const DEFAULT_DEPTH_IMAGE_FORMAT: Format = Format::D16_UNORM;
+/// Tried in order when the requested depth image format cannot be an optimal tiling attachment.
+const FALLBACK_DEPTH_IMAGE_FORMATS: [Format; 3] = [
+    Format::D32_SFLOAT,
+    Format::D24_UNORM_S8_UINT,
+    Format::D32_SFLOAT_S8_UINT,
+];
 const PRESENT_QUEUE_PRIORITY: f32 = 1.0;
 const TRANSFER_QUEUE_PRIORITY: f32 = 0.9;
 
+/// Display kinds of the platform with their surface extension, the ones offered by the loader
+/// are enabled for any window.
+fn platform_display_handles() -> Vec<(RawDisplayHandle, &'static str)> {
+    if cfg!(target_os = "windows") {
+        vec![(
+            RawDisplayHandle::Windows(WindowsDisplayHandle::empty()),
+            "VK_KHR_win32_surface",
+        )]
+    } else if cfg!(target_os = "macos") {
+        vec![(
+            RawDisplayHandle::AppKit(AppKitDisplayHandle::empty()),
+            "VK_EXT_metal_surface",
+        )]
+    } else if cfg!(target_os = "android") {
+        vec![(
+            RawDisplayHandle::Android(AndroidDisplayHandle::empty()),
+            "VK_KHR_android_surface",
+        )]
+    } else {
+        vec![
+            (
+                RawDisplayHandle::Xlib(XlibDisplayHandle::empty()),
+                "VK_KHR_xlib_surface",
+            ),
+            (
+                RawDisplayHandle::Xcb(XcbDisplayHandle::empty()),
+                "VK_KHR_xcb_surface",
+            ),
+            (
+                RawDisplayHandle::Wayland(WaylandDisplayHandle::empty()),
+                "VK_KHR_wayland_surface",
+            ),
+        ]
+    }
+}
+
+/// Names of the instance extensions offered by the loader and its implicit layers.
+fn available_instance_extensions(entry: &Entry) -> Result<FxHashSet<String>, RendererError> {
+    Ok(entry
+        .enumerate_instance_extension_properties()
+        .map_err(RendererError::Instance)?
+        .iter()
+        .map(|properties| {
+            unsafe { CStr::from_ptr(properties.extension_name.as_ptr() as *const c_char) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect())
+}
+
 pub struct RenderDeviceBuilder {
     vulkan_application_name: &'static str,
     sampler_anisotropy: Option<f32>,
     validation_level: Option<DebugUtilsMessageSeverityFlagsEXT>,
+    validation_output: ValidationOutput,
+    panic_on_validation_error: bool,
     device_id: Option<u32>,
-    // msaa_sample_counts: Option<SampleCountFlags>,
+    device_selector: Box<dyn DeviceSelector>,
+    on_device_recovered: Option<Box<dyn Fn(&RenderDevice) + Send + Sync>>,
+    msaa_sample_count: SampleCountFlags,
     depth_image_format: Format,
+    stencil_clear_value: u32,
     pipeline_cache_data: Option<Vec<u8>>,
     target_window_handles: Vec<WindowHandle>,
+    headless: bool,
+    hdr_output: bool,
 }
 
 impl Default for RenderDeviceBuilder {
@@ -49,10 +124,18 @@ impl Default for RenderDeviceBuilder {
             vulkan_application_name: DEFAULT_APP_NAME,
             sampler_anisotropy: None,
             validation_level: None,
+            validation_output: ValidationOutput::default(),
+            panic_on_validation_error: false,
             device_id: None,
+            device_selector: Box::new(PreferDiscrete),
+            on_device_recovered: None,
+            msaa_sample_count: SampleCountFlags::TYPE_1,
             depth_image_format: DEFAULT_DEPTH_IMAGE_FORMAT,
+            stencil_clear_value: 0,
             pipeline_cache_data: None,
             target_window_handles: vec![],
+            headless: false,
+            hdr_output: false,
         }
     }
 }
@@ -70,50 +153,123 @@ impl RenderDeviceBuilder {
         self.validation_level = Some(level);
         self
     }
+    /// Where validation messages go, the `log` crate with the default features.
+    pub fn validation_output(mut self, output: ValidationOutput) -> Self {
+        self.validation_output = output;
+        self
+    }
+    /// Panics on validation errors after they are reported, meant for tests. The panic
+    /// happens at the end of the next `render`, not in the driver's callback. Needs
+    /// `validation_level` including errors, `build` fails without a validation level.
+    pub fn panic_on_validation_error(mut self, panic_on_validation_error: bool) -> Self {
+        self.panic_on_validation_error = panic_on_validation_error;
+        self
+    }
+    /// Uses the device with the id, overrides the device selector.
     pub fn device_id(mut self, device_id: u32) -> Self {
         self.device_id = Some(device_id);
         self
     }
-    // pub fn msaa_sample_counts(mut self, msaa_sample_counts: SampleCountFlags) -> Self {
-    //     self.msaa_sample_counts = Some(msaa_sample_counts);
-    //     self
-    // }
+    /// Policy choosing among the devices able to render to the target windows,
+    /// `PreferDiscrete` by default.
+    pub fn device_selector(mut self, device_selector: impl DeviceSelector + 'static) -> Self {
+        self.device_selector = Box::new(device_selector);
+        self
+    }
+    /// Called with the new device by `RenderDevice::recover`, static vertices, indices and
+    /// textures of the lost device are invalid and have to be uploaded again.
+    pub fn on_device_recovered(
+        mut self,
+        on_device_recovered: impl Fn(&RenderDevice) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_device_recovered = Some(Box::new(on_device_recovered));
+        self
+    }
+    /// Samples per pixel of the color and depth attachments, e.g. `SampleCountFlags::TYPE_4`.
+    /// Multisampled images are resolved into the render target at the end of the render pass.
+    pub fn msaa_sample_count(mut self, msaa_sample_count: SampleCountFlags) -> Self {
+        self.msaa_sample_count = msaa_sample_count;
+        self
+    }
+    /// Falls back to the first supported of `D32_SFLOAT`, `D24_UNORM_S8_UINT` and
+    /// `D32_SFLOAT_S8_UINT` if the device cannot use `format` as a depth attachment.
+    /// Formats with a stencil component enable `MeshRenderer::stencil`.
     pub fn depth_image_format(mut self, format: Format) -> Self {
         self.depth_image_format = format;
         self
     }
+    pub fn stencil_clear_value(mut self, stencil_clear_value: u32) -> Self {
+        self.stencil_clear_value = stencil_clear_value;
+        self
+    }
+    /// Data exported by `RenderDevice::export_pipeline_cache`. Data from another device or
+    /// driver version is discarded.
     pub fn pipeline_cache_data(mut self, data: Vec<u8>) -> Self {
         self.pipeline_cache_data = Some(data);
         self
     }
+    /// Loads the data saved by `RenderDevice::save_pipeline_cache`, a missing file starts with
+    /// an empty cache.
+    pub fn load_pipeline_cache<P: AsRef<Path>>(mut self, path: P) -> Result<Self, RendererError> {
+        let path = path.as_ref();
+        match std::fs::read(path) {
+            Ok(data) => self.pipeline_cache_data = Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(RendererError::Io(format!("{}: {e}", path.display()))),
+        }
+        Ok(self)
+    }
+    /// Windows the chosen device must be able to present to. Other windows can be created after
+    /// the device is built, every surface extension of the platform the loader offers is
+    /// enabled; `RenderWindow::new` fails if the device cannot present to them.
     pub fn target_windows(mut self, handles: Vec<WindowHandle>) -> Self {
         self.target_window_handles = handles;
         self
     }
-    fn create_instance(&self) -> Arc<Instance> {
-        let entry = Entry::load().unwrap();
+    /// Build a device without surface and swapchain extensions, it can only render into
+    /// `OffscreenTarget`s. Target windows are ignored.
+    pub fn headless(mut self) -> Self {
+        self.headless = true;
+        self
+    }
+    /// Enables `VK_EXT_swapchain_colorspace` so surfaces can offer HDR color spaces to
+    /// `SwapchainConfig::hdr`. Ignored by headless devices and loaders without the extension,
+    /// HDR windows are tone mapped to SDR then.
+    pub fn hdr_output(mut self) -> Self {
+        self.hdr_output = true;
+        self
+    }
+    fn create_instance(
+        &self,
+        validation_errors: &Option<Arc<ValidationErrors>>,
+    ) -> Result<Arc<Instance>, RendererError> {
+        let entry = Entry::load().map_err(|e| RendererError::Loader(e.to_string()))?;
         let application_info = ApplicationInfo::builder()
             .app_name(self.vulkan_application_name)
             .engine_name(DEFAULT_ENGINE_NAME)
+            // for `DeviceInfo::device_uuid`, 1.0 devices still work with a 1.1 instance
+            .api_version(API_VERSION_1_1)
             .build();
         let mut instance_builder =
             Instance::builder(entry.clone()).application_info(application_info);
         if let Some(level) = self.validation_level {
             let layer =
                 unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0") };
+            let validation_output = self.validation_output.clone();
+            let validation_errors = validation_errors.clone();
             let debug_utils_messenger_callback = DebugUtilsMessengerCreateInfoEXT::builder()
-                .callback(|message_severity, message_type, p_callback_data| {
-                    let message_id_number = p_callback_data.message_id_number;
-                    let message_id_name = p_callback_data.p_message_id_name;
-                    let message = p_callback_data.p_message;
-                    println!(
-                        "{:?}:\n{:?} [{} ({})] : {}\n",
-                        message_severity,
+                .callback(move |message_severity, message_type, p_callback_data| {
+                    let message = ValidationMessage {
+                        severity: message_severity,
                         message_type,
-                        message_id_name,
-                        message_id_number.to_string(),
-                        message,
-                    );
+                        message_id_name: p_callback_data.p_message_id_name.to_string(),
+                        message_id_number: p_callback_data.message_id_number,
+                        message: p_callback_data.p_message.to_string(),
+                    };
+                    validation_output.emit(&message);
+                    if let Some(validation_errors) = &validation_errors {
+                        validation_errors.report(&message);
+                    }
                 })
                 .severity(level)
                 .build();
@@ -121,112 +277,219 @@ impl RenderDeviceBuilder {
                 .add_layer(layer)
                 .debug_utils_messenger_exts(vec![debug_utils_messenger_callback]);
         }
-        for window_handle in &self.target_window_handles {
-            for exts in enumerate_required_extensions(window_handle.display_handle).unwrap() {
+        let mut display_handles: Vec<_> = self
+            .window_handles()
+            .iter()
+            .map(|window_handle| window_handle.display_handle)
+            .collect();
+        if !self.headless {
+            // windows may be created after the device, not only the target windows
+            let available_extensions = available_instance_extensions(&entry)?;
+            let platform_display_handles: Vec<_> = platform_display_handles()
+                .into_iter()
+                .filter(|(_, extension)| available_extensions.contains(*extension))
+                .map(|(display_handle, _)| display_handle)
+                .collect();
+            if platform_display_handles.is_empty() {
+                return Err(RendererError::UnsupportedSurface);
+            }
+            display_handles.extend(platform_display_handles);
+            // without it surfaces only offer sRGB, `SwapchainConfig::hdr` tone maps to SDR then
+            if self.hdr_output && available_extensions.contains("VK_EXT_swapchain_colorspace") {
+                instance_builder =
+                    instance_builder.add_extension(&InstanceExtension::ExtSwapchainColorspace);
+            }
+        }
+        for display_handle in display_handles {
+            for exts in
+                enumerate_required_extensions(display_handle).map_err(RendererError::Instance)?
+            {
                 instance_builder = instance_builder.add_extension(&exts);
             }
         }
-        instance_builder.build().unwrap()
+        instance_builder.build().map_err(RendererError::Instance)
     }
-    fn create_physical_device(&self, instance: &Arc<Instance>) -> Arc<PhysicalDevice> {
+    fn create_physical_device(
+        &self,
+        instance: &Arc<Instance>,
+    ) -> Result<Arc<PhysicalDevice>, RendererError> {
         if let Some(device_id) = self.device_id {
             instance
-                .enumerate_physical_devices()
-                .unwrap()
+                .enumerate_physical_devices()?
                 .iter()
                 .find(|physical_device| {
                     physical_device.get_physical_device_properties().device_id == device_id
                 })
-                .expect(format!("no device id {device_id} found").as_str())
-                .clone()
+                .cloned()
+                .ok_or(RendererError::DeviceNotFound { device_id })
         } else {
-            self.choose_device(instance).expect("no available device")
+            self.choose_device(instance)
         }
     }
     fn handle_sampler_anisotropy(
         &self,
         physical_device: &PhysicalDevice,
         mut device_builder: DeviceBuilder,
-    ) -> DeviceBuilder {
+    ) -> Result<DeviceBuilder, RendererError> {
         let support_sampler_anisotropy = physical_device
             .get_physical_device_features()
             .contains(&PhysicalDeviceFeatures::SamplerAnisotropy.into());
         if !support_sampler_anisotropy && self.sampler_anisotropy.is_some() {
-            panic!("sampler anisotropy does not support")
+            return Err(RendererError::UnsupportedFeature(
+                "sampler anisotropy does not support".to_string(),
+            ));
         } else if support_sampler_anisotropy {
             device_builder = device_builder.add_feature(DeviceFeatures::SamplerAnisotropy);
             if let Some(sampler_anisotropy) = self.sampler_anisotropy {
                 let device_limits = physical_device.get_physical_device_properties().limits;
                 if device_limits.max_sampler_anisotropy < sampler_anisotropy {
-                    panic!("sampler anisotropy is large than supported")
+                    return Err(RendererError::UnsupportedFeature(format!(
+                        "sampler anisotropy {sampler_anisotropy} is large than supported {}",
+                        device_limits.max_sampler_anisotropy
+                    )));
                 }
             }
         }
-        device_builder
+        Ok(device_builder)
     }
-    fn device_score(physical_device: &PhysicalDevice) -> usize {
-        let mut score = 0;
+    fn rejected_device(
+        physical_device: &PhysicalDevice,
+        reason: RejectionReason,
+    ) -> RejectedDevice {
         let properties = physical_device.get_physical_device_properties();
-        // Discrete GPUs have a significant performance advantage
-        if properties.device_type == PhysicalDeviceType::DISCRETE_GPU {
-            score += 1000;
+        RejectedDevice {
+            device_name: device_name(&properties),
+            device_id: properties.device_id,
+            reason,
         }
-
-        // Maximum possible size of textures affects graphics quality
-        score += properties.limits.max_image_dimension2_d;
-
-        let features = physical_device.get_physical_device_features();
-        // Application can't function without geometry shaders
-        if !features.contains(&GeometryShader) {
-            return 0;
+    }
+    fn window_handles(&self) -> &[WindowHandle] {
+        if self.headless {
+            &[]
+        } else {
+            self.target_window_handles.as_slice()
         }
-        score as usize
-    }
-    fn choose_device(&self, instance: &Arc<Instance>) -> Option<Arc<PhysicalDevice>> {
-        let khr_surface_ext = instance
-            .get_extension::<{ PhysicalInstanceExtensionType::KhrSurface }>()
-            .unwrap();
-        let mut rank = BTreeMap::new();
-        let pdevices = instance.enumerate_physical_devices().unwrap();
+    }
+    /// Devices with a graphics queue family able to present to every target window, and the
+    /// rejected ones with the reason.
+    fn candidate_devices(
+        &self,
+        instance: &Arc<Instance>,
+    ) -> Result<(Vec<Arc<PhysicalDevice>>, Vec<RejectedDevice>), RendererError> {
+        let khr_surface_ext = if self.headless {
+            None
+        } else {
+            Some(
+                instance
+                    .get_extension::<{ PhysicalInstanceExtensionType::KhrSurface }>()
+                    .ok_or(RendererError::Instance(
+                        yarvk::Result::ERROR_EXTENSION_NOT_PRESENT,
+                    ))?,
+            )
+        };
+        let mut candidates = Vec::new();
+        let mut rejected = Vec::new();
+        let pdevices = instance.enumerate_physical_devices()?;
         for pdevice in &pdevices {
+            let mut suitable = false;
             'outer: for queue_family_properties in
                 &pdevice.get_physical_device_queue_family_properties()
             {
-                for window_handle in &self.target_window_handles {
-                    let surface = Surface::get_physical_device_surface_support(
-                        khr_surface_ext.clone(),
-                        window_handle.display_handle,
-                        window_handle.window_handle,
-                        queue_family_properties,
-                    );
-                    match surface {
-                        Ok(handle) => {
-                            if handle.is_none()
-                                || !queue_family_properties
-                                    .queue_flags
-                                    .contains(QueueFlags::GRAPHICS)
-                            {
+                if !queue_family_properties
+                    .queue_flags
+                    .contains(QueueFlags::GRAPHICS)
+                {
+                    continue 'outer;
+                }
+                if let Some(khr_surface_ext) = &khr_surface_ext {
+                    for window_handle in self.window_handles() {
+                        let surface = Surface::get_physical_device_surface_support(
+                            khr_surface_ext.clone(),
+                            window_handle.display_handle,
+                            window_handle.window_handle,
+                            queue_family_properties,
+                        );
+                        match surface {
+                            Ok(handle) => {
+                                if handle.is_none() {
+                                    continue 'outer;
+                                }
+                            }
+                            Err(_) => {
                                 continue 'outer;
                             }
                         }
-                        Err(_) => {
-                            continue 'outer;
-                        }
                     }
                 }
-                rank.insert(Self::device_score(pdevice), pdevice.clone());
+                suitable = true;
+                break;
+            }
+            if suitable {
+                candidates.push(pdevice.clone());
+            } else {
+                let has_graphics_queue = pdevice
+                    .get_physical_device_queue_family_properties()
+                    .iter()
+                    .any(|properties| properties.queue_flags.contains(QueueFlags::GRAPHICS));
+                let reason = if has_graphics_queue {
+                    RejectionReason::NoSurfaceSupport
+                } else {
+                    RejectionReason::NoGraphicsQueue
+                };
+                rejected.push(Self::rejected_device(pdevice, reason));
+            }
+        }
+        Ok((candidates, rejected))
+    }
+    fn choose_device(
+        &self,
+        instance: &Arc<Instance>,
+    ) -> Result<Arc<PhysicalDevice>, RendererError> {
+        let (candidates, mut rejected) = self.candidate_devices(instance)?;
+        if candidates.is_empty() {
+            return Err(RendererError::NoSuitableDevice { rejected });
+        }
+        let device_infos: Vec<_> = candidates
+            .iter()
+            .map(|pdevice| DeviceInfo::new(pdevice))
+            .collect();
+        match self.device_selector.select(&device_infos) {
+            Some(index) => Ok(candidates
+                .get(index)
+                .expect("device selector returned an index out of candidates")
+                .clone()),
+            None => {
+                rejected.extend(
+                    candidates.iter().map(|pdevice| {
+                        Self::rejected_device(pdevice, RejectionReason::NotSelected)
+                    }),
+                );
+                Err(RendererError::NoSuitableDevice { rejected })
             }
         }
-        Some(rank.into_iter().next_back()?.1)
+    }
+    /// Devices able to render to the target windows, or any graphics device when headless,
+    /// for letting users pick one. Creates a temporary vulkan instance.
+    pub fn list_devices(&self) -> Result<Vec<DeviceInfo>, RendererError> {
+        // nothing is rendered to check validation errors on
+        let instance = self.create_instance(&None)?;
+        let (candidates, _) = self.candidate_devices(&instance)?;
+        Ok(candidates
+            .iter()
+            .map(|pdevice| DeviceInfo::new(pdevice))
+            .collect())
     }
     fn create_device(
         &self,
         physical_device: &Arc<PhysicalDevice>,
-    ) -> (
-        Arc<Device>,
-        ParallelRecordingQueue, /*present*/
-        ParallelRecordingQueue, /*transform*/
-    ) {
+    ) -> Result<
+        (
+            Arc<Device>,
+            SharedQueue, /*present*/
+            SharedQueue, /*transform*/
+        ),
+        RendererError,
+    > {
         let mut present_queue_family = None;
         let mut transfer_queue_family = None;
         let properties = physical_device.get_physical_device_queue_family_properties();
@@ -245,14 +508,21 @@ impl RenderDeviceBuilder {
                 }
             }
         }
-        let surface_ext = physical_device
-            .instance
-            .get_extension::<{ PhysicalInstanceExtensionType::KhrSurface }>()
-            .unwrap();
-        let mut device_builder = Device::builder(&physical_device)
-            .add_extension(&DeviceExtensionType::KhrSwapchain(surface_ext));
-        device_builder = self.handle_sampler_anisotropy(physical_device, device_builder);
-        let present_queue_family = present_queue_family.unwrap();
+        let mut device_builder = Device::builder(&physical_device);
+        if !self.headless {
+            let surface_ext = physical_device
+                .instance
+                .get_extension::<{ PhysicalInstanceExtensionType::KhrSurface }>()
+                .ok_or(RendererError::Instance(
+                    yarvk::Result::ERROR_EXTENSION_NOT_PRESENT,
+                ))?;
+            device_builder =
+                device_builder.add_extension(&DeviceExtensionType::KhrSwapchain(surface_ext));
+        }
+        device_builder = self.handle_sampler_anisotropy(physical_device, device_builder)?;
+        let present_queue_family = present_queue_family.ok_or_else(|| {
+            RendererError::InsufficientQueues("no graphics queue family".to_string())
+        })?;
         let mut present_queue_create_info_builder =
             DeviceQueueCreateInfo::builder(present_queue_family.clone());
         present_queue_create_info_builder =
@@ -273,31 +543,70 @@ impl RenderDeviceBuilder {
         let present_queue_create_info = present_queue_create_info_builder.build();
         let (device, mut queues) = device_builder
             .add_queue_info(present_queue_create_info)
-            .build()
-            .unwrap();
-        let mut present_queues = queues.remove(present_queue_family).unwrap();
-        let present_queue = ParallelRecordingQueue::new(present_queues.pop().unwrap()).unwrap();
+            .build()?;
+        let mut present_queues = queues
+            .remove(present_queue_family)
+            .expect("internal error: present queue family not created");
+        let present_queue = Arc::new(Mutex::new(ParallelRecordingQueue::new(
+            present_queues
+                .pop()
+                .expect("internal error: present queue not created"),
+        )?));
 
-        let transfer_queue_family =
-            transfer_queue_family.expect("tyleri renderer need at least two queues for now");
-        let mut transfer_queues = queues.remove(transfer_queue_family).unwrap();
-        let transfer_queue = ParallelRecordingQueue::new(transfer_queues.pop().unwrap()).unwrap();
-        (device, present_queue, transfer_queue)
-    }
-    // fn handle_msaa_sample_counts(&self, device_limits: &PhysicalDeviceLimits) {
-    //     let supported_sample_counts = device_limits.framebuffer_color_sample_counts.as_raw()
-    //         & device_limits.framebuffer_depth_sample_counts.as_raw()
-    //         & device_limits.framebuffer_stencil_sample_counts.as_raw()
-    //         & device_limits
-    //             .framebuffer_no_attachments_sample_counts
-    //             .as_raw();
-    //     if let Some(sample_counts) = self.msaa_sample_counts {
-    //         if sample_counts.as_raw() & supported_sample_counts != sample_counts.as_raw() {
-    //             panic!("asked sample counts does not support")
-    //         }
-    //     }
-    // }
-    fn create_sampler(&self, device: &Arc<Device>) -> Arc<Sampler> {
+        let transfer_queue = match transfer_queue_family {
+            Some(transfer_queue_family) => queues
+                .remove(transfer_queue_family)
+                .and_then(|mut transfer_queues| transfer_queues.pop()),
+            None => present_queues.pop(),
+        };
+        let transfer_queue = match transfer_queue {
+            Some(transfer_queue) => {
+                Arc::new(Mutex::new(ParallelRecordingQueue::new(transfer_queue)?))
+            }
+            // the only queue of the device, uploads are serialized with presentation
+            None => present_queue.clone(),
+        };
+        Ok((device, present_queue, transfer_queue))
+    }
+    fn handle_msaa_sample_count(
+        &self,
+        device_limits: &PhysicalDeviceLimits,
+    ) -> Result<SampleCountFlags, RendererError> {
+        let sample_count = self.msaa_sample_count;
+        if !sample_count.as_raw().is_power_of_two() {
+            return Err(RendererError::UnsupportedFeature(format!(
+                "msaa sample count must be a single count, got {sample_count:?}"
+            )));
+        }
+        let supported_sample_counts = device_limits.framebuffer_color_sample_counts
+            & device_limits.framebuffer_depth_sample_counts;
+        if !supported_sample_counts.contains(sample_count) {
+            return Err(RendererError::UnsupportedFeature(format!(
+                "msaa sample count {sample_count:?}, supported: {supported_sample_counts:?}"
+            )));
+        }
+        Ok(sample_count)
+    }
+    fn handle_depth_image_format(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> Result<Format, RendererError> {
+        std::iter::once(self.depth_image_format)
+            .chain(FALLBACK_DEPTH_IMAGE_FORMATS)
+            .find(|format| {
+                physical_device
+                    .get_physical_device_format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .ok_or_else(|| {
+                RendererError::UnsupportedFeature(format!(
+                    "depth image format {:?} and its fallbacks do not support",
+                    self.depth_image_format
+                ))
+            })
+    }
+    fn create_sampler(&self, device: &Arc<Device>) -> Result<Arc<Sampler>, RendererError> {
         // create sampler
         let mut sampler_builder = Sampler::builder(&device)
             .mag_filter(Filter::LINEAR)
@@ -316,39 +625,66 @@ impl RenderDeviceBuilder {
             sampler_builder =
                 sampler_builder.max_anisotropy(sampler_anisotropy, anisotropy_feature);
         }
-        sampler_builder.build().unwrap()
+        Ok(sampler_builder.build()?)
     }
-    fn create_pipeline_cache(&self, device: &Arc<Device>) -> PipelineCacheImpl<false> {
+    fn create_pipeline_cache(
+        &self,
+        device: &Arc<Device>,
+    ) -> Result<PipelineCacheImpl<false>, RendererError> {
         let mut pipeline_cache_builder = PipelineCache::builder(&device);
-        if let Some(pipeline_cache_data) = &self.pipeline_cache_data {
-            // TODO check if cache is valid
+        let properties = device.physical_device.get_physical_device_properties();
+        let pipeline_cache_data = self
+            .pipeline_cache_data
+            .as_ref()
+            // stale data may crash drivers, start with an empty cache instead
+            .filter(|data| is_valid_pipeline_cache(data, &properties));
+        if let Some(pipeline_cache_data) = pipeline_cache_data {
             pipeline_cache_builder =
                 pipeline_cache_builder.initial_data(pipeline_cache_data.as_slice());
         }
-        pipeline_cache_builder
-            .build_internally_synchronized()
-            .unwrap()
-    }
-    pub fn build(self) -> RenderDevice {
-        let instance = self.create_instance();
-        let pdevice = self.create_physical_device(&instance);
-        let (device, present_queue, transfer_queue) = self.create_device(&pdevice);
-        let present_queue_family = present_queue.queue_family_property.clone();
-        let present_queues = SegQueue::new();
-        present_queues.push(present_queue);
-        // self.handle_msaa_sample_counts(&pdevice.get_physical_device_properties().limits);
-        let default_sampler = self.create_sampler(&device);
-        let pipeline_cache = self.create_pipeline_cache(&device);
+        Ok(pipeline_cache_builder.build_internally_synchronized()?)
+    }
+    pub fn build(self) -> Result<RenderDevice, RendererError> {
+        if self.panic_on_validation_error && self.validation_level.is_none() {
+            return Err(RendererError::UnsupportedFeature(
+                "panic on validation error needs a validation level".to_string(),
+            ));
+        }
+        Arc::new(self).create_render_device()
+    }
+    /// Builds a device with the settings, also used to rebuild it by `RenderDevice::recover`.
+    pub(crate) fn create_render_device(self: &Arc<Self>) -> Result<RenderDevice, RendererError> {
+        let validation_errors = self
+            .panic_on_validation_error
+            .then(|| Arc::new(ValidationErrors::default()));
+        let instance = self.create_instance(&validation_errors)?;
+        let pdevice = self.create_physical_device(&instance)?;
+        let (device, present_queue, transfer_queue) = self.create_device(&pdevice)?;
+        let present_queue_family = present_queue.lock().queue_family_property.clone();
+        let depth_image_format = self.handle_depth_image_format(&pdevice)?;
+        let msaa_sample_count =
+            self.handle_msaa_sample_count(&pdevice.get_physical_device_properties().limits)?;
+        let default_sampler = self.create_sampler(&device)?;
+        let pipeline_cache = self.create_pipeline_cache(&device)?;
         let single_image_descriptor_set_layout = SingleImageDescriptorLayout::new(&default_sampler);
         let memory_allocator = MemoryAllocator::new(&device, transfer_queue);
-        RenderDevice {
+        Ok(RenderDevice {
             device,
             single_image_descriptor_set_layout,
             present_queue_family,
-            present_queues,
+            present_queue,
             memory_allocator,
             pipeline_cache,
-            depth_image_format: self.depth_image_format,
+            depth_image_format,
+            stencil_clear_value: self.stencil_clear_value,
+            msaa_sample_count,
+            validation_errors,
+            builder: self.clone(),
+        })
+    }
+    pub(crate) fn device_recovered(&self, render_device: &RenderDevice) {
+        if let Some(on_device_recovered) = &self.on_device_recovered {
+            on_device_recovered(render_device);
         }
     }
 }
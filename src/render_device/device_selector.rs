@@ -0,0 +1,324 @@
+use std::ffi::{c_char, CStr};
+
+use yarvk::device_features::PhysicalDeviceFeatures::SamplerAnisotropy;
+use yarvk::physical_device::PhysicalDevice;
+use yarvk::{
+    MemoryHeapFlags, PhysicalDeviceProperties, PhysicalDeviceType, SampleCountFlags,
+    API_VERSION_1_1,
+};
+
+/// A physical device able to run the renderer, as listed by `RenderDeviceBuilder::list_devices`.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub device_name: String,
+    pub device_id: u32,
+    pub vendor_id: u32,
+    /// None for Vulkan 1.0 devices, they cannot report it.
+    pub device_uuid: Option<[u8; 16]>,
+    pub device_type: PhysicalDeviceType,
+    pub api_version: u32,
+    pub driver_version: u32,
+    /// Total size of the device local memory heaps in bytes.
+    pub device_local_memory: u64,
+    pub max_image_dimension2_d: u32,
+    /// Sample counts usable with `RenderDeviceBuilder::msaa_sample_count`.
+    pub msaa_sample_counts: SampleCountFlags,
+    pub sampler_anisotropy: bool,
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(physical_device: &PhysicalDevice) -> Self {
+        let properties = physical_device.get_physical_device_properties();
+        let device_local_memory = physical_device
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+        Self {
+            device_name: device_name(&properties),
+            device_id: properties.device_id,
+            vendor_id: properties.vendor_id,
+            // physical device properties 2 is core since 1.1, the instance asks for 1.1
+            device_uuid: (properties.api_version >= API_VERSION_1_1).then(|| {
+                physical_device
+                    .get_physical_device_id_properties()
+                    .device_uuid
+            }),
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            driver_version: properties.driver_version,
+            device_local_memory,
+            max_image_dimension2_d: properties.limits.max_image_dimension2_d,
+            msaa_sample_counts: properties.limits.framebuffer_color_sample_counts
+                & properties.limits.framebuffer_depth_sample_counts,
+            sampler_anisotropy: physical_device
+                .get_physical_device_features()
+                .contains(&SamplerAnisotropy),
+        }
+    }
+}
+
+pub(crate) fn device_name(properties: &PhysicalDeviceProperties) -> String {
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr() as *const c_char) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Picks the device `RenderDeviceBuilder::build` creates, out of the devices that can render
+/// to the target windows.
+pub trait DeviceSelector: Send + Sync {
+    /// Index of the chosen device in `candidates`, none if no candidate is acceptable.
+    /// `candidates` is never empty and keeps the order the driver enumerates devices.
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize>;
+}
+
+const DISCRETE_FIRST: [PhysicalDeviceType; 4] = [
+    PhysicalDeviceType::DISCRETE_GPU,
+    PhysicalDeviceType::INTEGRATED_GPU,
+    PhysicalDeviceType::VIRTUAL_GPU,
+    PhysicalDeviceType::CPU,
+];
+const INTEGRATED_FIRST: [PhysicalDeviceType; 4] = [
+    PhysicalDeviceType::INTEGRATED_GPU,
+    PhysicalDeviceType::DISCRETE_GPU,
+    PhysicalDeviceType::VIRTUAL_GPU,
+    PhysicalDeviceType::CPU,
+];
+
+/// Picks the candidate with the best device type in `order`, ties go to the device with
+/// more device local memory, then to the first enumerated one.
+fn select_by_type_order<'a>(
+    candidates: impl Iterator<Item = (usize, &'a DeviceInfo)>,
+    order: &[PhysicalDeviceType],
+) -> Option<usize> {
+    let type_rank = |device_type: PhysicalDeviceType| {
+        order
+            .iter()
+            .position(|preferred| *preferred == device_type)
+            .unwrap_or(order.len())
+    };
+    candidates
+        // min_by_key keeps the first of equal elements
+        .min_by_key(|(_, device)| {
+            (
+                type_rank(device.device_type),
+                u64::MAX - device.device_local_memory,
+            )
+        })
+        .map(|(index, _)| index)
+}
+
+/// Discrete GPUs first, then integrated, virtual and software ones. The default.
+pub struct PreferDiscrete;
+
+impl DeviceSelector for PreferDiscrete {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        select_by_type_order(candidates.iter().enumerate(), &DISCRETE_FIRST)
+    }
+}
+
+/// Integrated GPUs first to save battery, then discrete, virtual and software ones.
+pub struct PreferIntegrated;
+
+impl DeviceSelector for PreferIntegrated {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        select_by_type_order(candidates.iter().enumerate(), &INTEGRATED_FIRST)
+    }
+}
+
+/// The first device whose name contains the string, case insensitive.
+pub struct ByName(pub String);
+
+impl DeviceSelector for ByName {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        let name = self.0.to_lowercase();
+        candidates
+            .iter()
+            .position(|device| device.device_name.to_lowercase().contains(&name))
+    }
+}
+
+/// The preferred device of the vendor by `PreferDiscrete`, e.g. `0x10DE` for NVIDIA.
+pub struct ByVendorId(pub u32);
+
+impl DeviceSelector for ByVendorId {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        select_by_type_order(
+            candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, device)| device.vendor_id == self.0),
+            &DISCRETE_FIRST,
+        )
+    }
+}
+
+/// The device with the UUID, stable across reboots and driver enumeration orders. Devices
+/// without a UUID never match.
+pub struct ByUuid(pub [u8; 16]);
+
+impl DeviceSelector for ByUuid {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        candidates
+            .iter()
+            .position(|device| device.device_uuid == Some(self.0))
+    }
+}
+
+/// Reads the device from an environment variable, falls back to another selector if the
+/// variable is not set. A decimal value is a device id, anything else matches device names
+/// like `ByName`. A set variable matching no device fails the selection.
+pub struct FromEnv {
+    pub variable: String,
+    pub fallback: Box<dyn DeviceSelector>,
+}
+
+impl FromEnv {
+    pub fn new(variable: impl Into<String>, fallback: impl DeviceSelector + 'static) -> Self {
+        Self {
+            variable: variable.into(),
+            fallback: Box::new(fallback),
+        }
+    }
+}
+
+impl DeviceSelector for FromEnv {
+    fn select(&self, candidates: &[DeviceInfo]) -> Option<usize> {
+        match std::env::var(&self.variable) {
+            Ok(value) => match value.trim().parse::<u32>() {
+                Ok(device_id) => candidates
+                    .iter()
+                    .position(|device| device.device_id == device_id),
+                Err(_) => ByName(value.trim().to_string()).select(candidates),
+            },
+            Err(_) => self.fallback.select(candidates),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(
+        device_name: &str,
+        device_id: u32,
+        vendor_id: u32,
+        device_type: PhysicalDeviceType,
+        device_local_memory: u64,
+    ) -> DeviceInfo {
+        DeviceInfo {
+            device_name: device_name.to_string(),
+            device_id,
+            vendor_id,
+            device_uuid: Some([device_id as u8; 16]),
+            device_type,
+            api_version: API_VERSION_1_1,
+            driver_version: 1,
+            device_local_memory,
+            max_image_dimension2_d: 16384,
+            msaa_sample_counts: SampleCountFlags::TYPE_1,
+            sampler_anisotropy: true,
+        }
+    }
+
+    /// Software, integrated, discrete and a second, larger discrete device.
+    fn devices() -> Vec<DeviceInfo> {
+        vec![
+            device("llvmpipe", 1, 0x10005, PhysicalDeviceType::CPU, 0),
+            device(
+                "Intel UHD Graphics",
+                2,
+                0x8086,
+                PhysicalDeviceType::INTEGRATED_GPU,
+                1 << 30,
+            ),
+            device(
+                "NVIDIA GeForce RTX 3060",
+                3,
+                0x10de,
+                PhysicalDeviceType::DISCRETE_GPU,
+                12 << 30,
+            ),
+            device(
+                "AMD Radeon RX 6900 XT",
+                4,
+                0x1002,
+                PhysicalDeviceType::DISCRETE_GPU,
+                16 << 30,
+            ),
+        ]
+    }
+
+    #[test]
+    fn prefer_discrete() {
+        let devices = devices();
+        assert_eq!(PreferDiscrete.select(&devices), Some(3));
+        assert_eq!(PreferDiscrete.select(&devices[..2]), Some(1));
+        assert_eq!(PreferDiscrete.select(&devices[..1]), Some(0));
+    }
+
+    #[test]
+    fn prefer_discrete_keeps_enumeration_order_on_ties() {
+        let mut devices = devices();
+        devices[3].device_local_memory = devices[2].device_local_memory;
+        assert_eq!(PreferDiscrete.select(&devices), Some(2));
+    }
+
+    #[test]
+    fn prefer_integrated() {
+        let devices = devices();
+        assert_eq!(PreferIntegrated.select(&devices), Some(1));
+        assert_eq!(PreferIntegrated.select(&devices[2..]), Some(1));
+    }
+
+    #[test]
+    fn by_name() {
+        let devices = devices();
+        assert_eq!(ByName("geforce".to_string()).select(&devices), Some(2));
+        assert_eq!(ByName("RADEON".to_string()).select(&devices), Some(3));
+        assert_eq!(ByName("Apple".to_string()).select(&devices), None);
+    }
+
+    #[test]
+    fn by_vendor_id() {
+        let mut devices = devices();
+        devices.push(device(
+            "NVIDIA GeForce GTX 1050",
+            5,
+            0x10de,
+            PhysicalDeviceType::DISCRETE_GPU,
+            4 << 30,
+        ));
+        assert_eq!(ByVendorId(0x10de).select(&devices), Some(2));
+        assert_eq!(ByVendorId(0x8086).select(&devices), Some(1));
+        assert_eq!(ByVendorId(0x13b5).select(&devices), None);
+    }
+
+    #[test]
+    fn by_uuid() {
+        let mut devices = devices();
+        assert_eq!(ByUuid([3; 16]).select(&devices), Some(2));
+        assert_eq!(ByUuid([9; 16]).select(&devices), None);
+        devices[2].device_uuid = None;
+        assert_eq!(ByUuid([3; 16]).select(&devices), None);
+    }
+
+    #[test]
+    fn from_env() {
+        let devices = devices();
+        let variable = "TYLERI_TEST_DEVICE_SELECTOR";
+        let selector = FromEnv::new(variable, PreferIntegrated);
+        std::env::remove_var(variable);
+        assert_eq!(selector.select(&devices), Some(1));
+        std::env::set_var(variable, "3");
+        assert_eq!(selector.select(&devices), Some(2));
+        std::env::set_var(variable, " radeon ");
+        assert_eq!(selector.select(&devices), Some(3));
+        std::env::set_var(variable, "42");
+        assert_eq!(selector.select(&devices), None);
+        std::env::remove_var(variable);
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+
+use yarvk::PhysicalDeviceProperties;
+
+use crate::render_device::RenderDevice;
+use crate::RendererError;
+
+/// Length of `VkPipelineCacheHeaderVersionOne`.
+const HEADER_LENGTH: usize = 32;
+const HEADER_VERSION_ONE: u32 = 1;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(
+        data[offset..offset + 4]
+            .try_into()
+            .expect("internal error: slice is not 4 bytes"),
+    )
+}
+
+/// Whether `data` was exported from a device with the same vendor, device and
+/// `pipelineCacheUUID`, the header fields are stored little endian.
+pub(crate) fn is_valid_pipeline_cache(data: &[u8], properties: &PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_LENGTH {
+        return false;
+    }
+    let header_length = read_u32(data, 0) as usize;
+    header_length >= HEADER_LENGTH
+        && header_length <= data.len()
+        && read_u32(data, 4) == HEADER_VERSION_ONE
+        && read_u32(data, 8) == properties.vendor_id
+        && read_u32(data, 12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+/// `path` with `.tmp` appended, replacing its extension could clash with a sibling file.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    temporary_path.into()
+}
+
+impl RenderDevice {
+    /// Data of the pipeline cache, feed it to `RenderDeviceBuilder::pipeline_cache_data` on
+    /// the next launch to skip compiling pipelines again.
+    pub fn export_pipeline_cache(&self) -> Result<Vec<u8>, RendererError> {
+        Ok(self.pipeline_cache.get_data()?)
+    }
+    /// Writes `export_pipeline_cache` to `path`, through a temporary file so an interrupted
+    /// write never leaves a truncated cache behind.
+    pub fn save_pipeline_cache<P: AsRef<Path>>(&self, path: P) -> Result<(), RendererError> {
+        let path = path.as_ref();
+        let data = self.export_pipeline_cache()?;
+        let temporary_path = temporary_path(path);
+        std::fs::write(&temporary_path, data)
+            .and_then(|_| std::fs::rename(&temporary_path, path))
+            .map_err(|e| RendererError::Io(format!("{}: {e}", path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VENDOR_ID: u32 = 0x10de;
+    const DEVICE_ID: u32 = 0x2484;
+    const UUID: [u8; 16] = [7; 16];
+
+    fn properties() -> PhysicalDeviceProperties {
+        PhysicalDeviceProperties {
+            vendor_id: VENDOR_ID,
+            device_id: DEVICE_ID,
+            pipeline_cache_uuid: UUID,
+            ..Default::default()
+        }
+    }
+
+    fn cache_data(version: u32, vendor_id: u32, device_id: u32, uuid: [u8; 16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_LENGTH as u32).to_le_bytes());
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_id.to_le_bytes());
+        data.extend_from_slice(&uuid);
+        // pipelines following the header
+        data.extend_from_slice(&[0xab; 64]);
+        data
+    }
+
+    #[test]
+    fn temporary_path_keeps_extension() {
+        assert_eq!(
+            temporary_path(Path::new("cache/pipelines.bin")),
+            Path::new("cache/pipelines.bin.tmp")
+        );
+        assert_eq!(
+            temporary_path(Path::new("pipelines")),
+            Path::new("pipelines.tmp")
+        );
+    }
+
+    #[test]
+    fn accepts_matching_header() {
+        let data = cache_data(HEADER_VERSION_ONE, VENDOR_ID, DEVICE_ID, UUID);
+        assert!(is_valid_pipeline_cache(&data, &properties()));
+        assert!(is_valid_pipeline_cache(
+            &data[..HEADER_LENGTH],
+            &properties()
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let data = cache_data(HEADER_VERSION_ONE, VENDOR_ID, DEVICE_ID, UUID);
+        assert!(!is_valid_pipeline_cache(&[], &properties()));
+        assert!(!is_valid_pipeline_cache(
+            &data[..HEADER_LENGTH - 1],
+            &properties()
+        ));
+    }
+
+    #[test]
+    fn rejects_header_length_out_of_data() {
+        let mut data = cache_data(HEADER_VERSION_ONE, VENDOR_ID, DEVICE_ID, UUID);
+        data[0..4].copy_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+        data[0..4].copy_from_slice(&(HEADER_LENGTH as u32 - 1).to_le_bytes());
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+    }
+
+    #[test]
+    fn rejects_wrong_version() {
+        let data = cache_data(HEADER_VERSION_ONE + 1, VENDOR_ID, DEVICE_ID, UUID);
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+    }
+
+    #[test]
+    fn rejects_other_device() {
+        let data = cache_data(HEADER_VERSION_ONE, VENDOR_ID + 1, DEVICE_ID, UUID);
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+        let data = cache_data(HEADER_VERSION_ONE, VENDOR_ID, DEVICE_ID + 1, UUID);
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+    }
+
+    #[test]
+    fn rejects_wrong_uuid() {
+        let mut uuid = UUID;
+        uuid[15] ^= 1;
+        let data = cache_data(HEADER_VERSION_ONE, VENDOR_ID, DEVICE_ID, uuid);
+        assert!(!is_valid_pipeline_cache(&data, &properties()));
+    }
+}
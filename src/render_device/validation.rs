@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use yarvk::{DebugUtilsMessageSeverityFlagsEXT, DebugUtilsMessageTypeFlagsEXT};
+
+/// A message of the validation layer.
+#[derive(Clone, Debug)]
+pub struct ValidationMessage {
+    pub severity: DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: DebugUtilsMessageTypeFlagsEXT,
+    pub message_id_name: String,
+    pub message_id_number: i32,
+    pub message: String,
+}
+
+impl ValidationMessage {
+    fn is_error(&self) -> bool {
+        self.severity
+            .contains(DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    }
+}
+
+/// Where `RenderDeviceBuilder::validation_level` messages go. `Tracing` by default when the
+/// feature is on, else `Log`, and stdout only without both features.
+#[derive(Clone, Default)]
+pub enum ValidationOutput {
+    /// Prints to stdout.
+    #[cfg_attr(not(any(feature = "log", feature = "tracing")), default)]
+    Stdout,
+    /// Logs to the `log` crate with target `tyleri_renderer::validation`. Errors, warnings and
+    /// infos keep their level, verbose messages are logged as trace.
+    #[cfg(feature = "log")]
+    #[cfg_attr(not(feature = "tracing"), default)]
+    Log,
+    /// Emits `tracing` events with target `tyleri_renderer::validation`, message type and id
+    /// are recorded as fields.
+    #[cfg(feature = "tracing")]
+    #[default]
+    Tracing,
+    /// Calls the function for every message, from any thread the driver reports on. The
+    /// function must not panic, it cannot unwind through the driver.
+    Callback(Arc<dyn Fn(&ValidationMessage) + Send + Sync>),
+}
+
+impl ValidationOutput {
+    pub(crate) fn emit(&self, message: &ValidationMessage) {
+        match self {
+            ValidationOutput::Stdout => println!(
+                "{:?}:\n{:?} [{} ({})] : {}\n",
+                message.severity,
+                message.message_type,
+                message.message_id_name,
+                message.message_id_number,
+                message.message,
+            ),
+            #[cfg(feature = "log")]
+            ValidationOutput::Log => log_message(message),
+            #[cfg(feature = "tracing")]
+            ValidationOutput::Tracing => trace_message(message),
+            ValidationOutput::Callback(callback) => callback(message),
+        }
+    }
+}
+
+/// Validation errors counted in the driver's callback, the panic of
+/// `RenderDeviceBuilder::panic_on_validation_error` happens on the next check instead.
+#[derive(Default)]
+pub(crate) struct ValidationErrors {
+    count: AtomicUsize,
+}
+
+impl ValidationErrors {
+    pub(crate) fn report(&self, message: &ValidationMessage) {
+        if message.is_error() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    /// Panics if errors were reported since the last check.
+    pub(crate) fn check(&self) {
+        let count = self.count.swap(0, Ordering::Relaxed);
+        if count > 0 {
+            panic!("{count} vulkan validation errors, see the validation output");
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_message(message: &ValidationMessage) {
+    let level = if message.is_error() {
+        log::Level::Error
+    } else if message
+        .severity
+        .contains(DebugUtilsMessageSeverityFlagsEXT::WARNING)
+    {
+        log::Level::Warn
+    } else if message
+        .severity
+        .contains(DebugUtilsMessageSeverityFlagsEXT::INFO)
+    {
+        log::Level::Info
+    } else {
+        log::Level::Trace
+    };
+    log::log!(
+        target: "tyleri_renderer::validation",
+        level,
+        "{:?} [{} ({})] {}",
+        message.message_type,
+        message.message_id_name,
+        message.message_id_number,
+        message.message
+    );
+}
+
+#[cfg(feature = "tracing")]
+fn trace_message(message: &ValidationMessage) {
+    macro_rules! event {
+        ($level:expr) => {
+            tracing::event!(
+                target: "tyleri_renderer::validation",
+                $level,
+                message_type = ?message.message_type,
+                message_id_name = %message.message_id_name,
+                message_id_number = message.message_id_number,
+                "{}",
+                message.message
+            )
+        };
+    }
+    if message.is_error() {
+        event!(tracing::Level::ERROR)
+    } else if message
+        .severity
+        .contains(DebugUtilsMessageSeverityFlagsEXT::WARNING)
+    {
+        event!(tracing::Level::WARN)
+    } else if message
+        .severity
+        .contains(DebugUtilsMessageSeverityFlagsEXT::INFO)
+    {
+        event!(tracing::Level::INFO)
+    } else {
+        event!(tracing::Level::TRACE)
+    }
+}
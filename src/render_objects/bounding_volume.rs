@@ -0,0 +1,215 @@
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+use tyleri_api::data_structure::vertices::Vertex;
+
+/// Local space bounds of a mesh, tested against the view frustum of each camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundingVolume {
+    Aabb { min: Vec3, max: Vec3 },
+    Sphere { center: Vec3, radius: f32 },
+}
+
+impl BoundingVolume {
+    /// The axis aligned box of the vertex positions, none without vertices.
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        let mut positions = vertices.iter().map(|vertex| Vec3::from(vertex.pos));
+        let first = positions.next()?;
+        let (min, max) = positions.fold((first, first), |(min, max), position| {
+            (min.min(position), max.max(position))
+        });
+        Some(BoundingVolume::Aabb { min, max })
+    }
+    pub(crate) fn center(&self) -> Vec3 {
+        match *self {
+            BoundingVolume::Aabb { min, max } => (min + max) / 2.0,
+            BoundingVolume::Sphere { center, .. } => center,
+        }
+    }
+    pub(crate) fn is_visible(&self, model: &Mat4, frustum: &Frustum) -> bool {
+        match *self {
+            BoundingVolume::Aabb { min, max } => {
+                let center = model.transform_point3((min + max) / 2.0);
+                let half_extent = (max - min) / 2.0;
+                // extent of the transformed box along the world axes
+                let world_half_extent = Vec3::new(
+                    model.row(0).xyz().abs().dot(half_extent),
+                    model.row(1).xyz().abs().dot(half_extent),
+                    model.row(2).xyz().abs().dot(half_extent),
+                );
+                frustum.intersects_aabb(center, world_half_extent)
+            }
+            BoundingVolume::Sphere { center, radius } => {
+                let scale = model
+                    .x_axis
+                    .xyz()
+                    .length()
+                    .max(model.y_axis.xyz().length())
+                    .max(model.z_axis.xyz().length());
+                frustum.intersects_sphere(model.transform_point3(center), radius * scale)
+            }
+        }
+    }
+}
+
+/// World space planes of a view frustum, normals point inwards.
+pub(crate) struct Frustum {
+    planes: Vec<Vec4>,
+}
+
+impl Frustum {
+    /// Extracts the planes of a projection mapping depth to 0..1. Planes at infinity, e.g. the
+    /// far plane of an infinite projection, have no normal and are skipped.
+    pub(crate) fn from_view_projection(view_projection: &Mat4) -> Self {
+        let (row0, row1, row2, row3) = (
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        );
+        let planes = [
+            row3 + row0,
+            row3 - row0,
+            row3 + row1,
+            row3 - row1,
+            row2,
+            row3 - row2,
+        ]
+        .into_iter()
+        .filter_map(|plane| {
+            let length = plane.xyz().length();
+            (length > f32::EPSILON).then(|| plane / length)
+        })
+        .collect();
+        Self { planes }
+    }
+    fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.xyz().dot(center) + plane.w >= -radius)
+    }
+    fn intersects_aabb(&self, center: Vec3, half_extent: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let radius = plane.xyz().abs().dot(half_extent);
+            plane.xyz().dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(pos: [f32; 3]) -> Vertex {
+        Vertex {
+            pos,
+            uv: [0.0, 0.0],
+        }
+    }
+
+    fn unit_box() -> BoundingVolume {
+        BoundingVolume::from_vertices(&[vertex([-1.0, -1.0, -1.0]), vertex([1.0, 1.0, 1.0])])
+            .unwrap()
+    }
+
+    /// Camera at the origin looking down -z.
+    fn frustum(projection: Mat4) -> Frustum {
+        Frustum::from_view_projection(&projection)
+    }
+
+    /// 90 degrees field of view, the frustum is 10 wide at a depth of 5.
+    fn perspective() -> Frustum {
+        frustum(Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.1, 100.0))
+    }
+
+    #[test]
+    fn extracts_normalized_planes() {
+        let frustum = perspective();
+        assert_eq!(frustum.planes.len(), 6);
+        for plane in &frustum.planes {
+            assert!((plane.xyz().length() - 1.0).abs() < 1e-5);
+        }
+        assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -5.0), 0.0));
+        assert!(frustum.intersects_sphere(Vec3::new(4.9, 4.9, -5.0), 0.0));
+        // behind the camera, before the near plane, left of it and beyond the far plane
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, 5.0), 0.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, -0.05), 0.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(-5.1, 0.0, -5.0), 0.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, -101.0), 0.0));
+    }
+
+    #[test]
+    fn skips_planes_at_infinity() {
+        for projection in [
+            Mat4::perspective_infinite_rh(90f32.to_radians(), 1.0, 0.1),
+            Mat4::perspective_infinite_reverse_rh(90f32.to_radians(), 1.0, 0.1),
+        ] {
+            let frustum = frustum(projection);
+            assert_eq!(frustum.planes.len(), 5);
+            assert!(frustum.planes.iter().all(|plane| !plane.is_nan()));
+            assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -1.0e6), 0.0));
+            assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, 5.0), 0.0));
+        }
+    }
+
+    #[test]
+    fn from_vertices() {
+        assert_eq!(BoundingVolume::from_vertices(&[]), None);
+        let bounds = BoundingVolume::from_vertices(&[
+            vertex([1.0, -2.0, 0.5]),
+            vertex([-1.0, 3.0, 0.0]),
+            vertex([0.0, 0.0, -4.0]),
+        ]);
+        assert_eq!(
+            bounds,
+            Some(BoundingVolume::Aabb {
+                min: Vec3::new(-1.0, -2.0, -4.0),
+                max: Vec3::new(1.0, 3.0, 0.5),
+            })
+        );
+        assert_eq!(bounds.unwrap().center(), Vec3::new(0.0, 0.5, -1.75));
+    }
+
+    #[test]
+    fn aabb_visibility() {
+        let frustum = perspective();
+        let bounds = unit_box();
+        let visible =
+            |translation: Vec3| bounds.is_visible(&Mat4::from_translation(translation), &frustum);
+        assert!(visible(Vec3::new(0.0, 0.0, -5.0)));
+        assert!(!visible(Vec3::new(0.0, 0.0, 5.0)));
+        assert!(!visible(Vec3::new(-8.0, 0.0, -5.0)));
+        assert!(!visible(Vec3::new(0.0, 0.0, -102.0)));
+        // straddling the left and the far plane
+        assert!(visible(Vec3::new(-5.5, 0.0, -5.0)));
+        assert!(visible(Vec3::new(0.0, 0.0, -100.5)));
+    }
+
+    #[test]
+    fn scaled_aabb_visibility() {
+        let frustum = perspective();
+        let bounds = unit_box();
+        let translation = Mat4::from_translation(Vec3::new(-12.0, 0.0, -5.0));
+        assert!(!bounds.is_visible(&translation, &frustum));
+        let scaled = translation * Mat4::from_scale(Vec3::splat(4.0));
+        assert!(bounds.is_visible(&scaled, &frustum));
+    }
+
+    #[test]
+    fn sphere_visibility() {
+        let frustum = perspective();
+        let bounds = BoundingVolume::Sphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        };
+        let visible = |model: Mat4| bounds.is_visible(&model, &frustum);
+        assert!(visible(Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0))));
+        assert!(!visible(Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0))));
+        // straddling the left plane
+        assert!(visible(Mat4::from_translation(Vec3::new(-5.5, 0.0, -5.0))));
+        let translation = Mat4::from_translation(Vec3::new(-12.0, 0.0, -5.0));
+        assert!(!visible(translation));
+        // the largest axis scale grows the radius
+        assert!(visible(
+            translation * Mat4::from_scale(Vec3::new(1.0, 1.0, 6.0))
+        ));
+    }
+}
@@ -1,20 +1,101 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::render_objects::mesh_renderer::MeshRenderer;
-use glam::Mat4;
+use crate::render_objects::mesh_renderer::{MeshBatch, MeshDraw, MeshRenderer, RenderQueue};
+use glam::{Mat4, Vec3, Vec4};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
 use yarvk::{Rect2D, Viewport};
 
-use crate::render_objects::ParallelGroup;
+use crate::render_objects::bounding_volume::Frustum;
+use crate::render_objects::instanced_mesh_renderer::InstancedMeshRenderer;
 use crate::render_scene::RenderScene;
+use crate::render_target::render_texture::RenderTexture;
+
+/// Where a `Camera` renders into.
+#[derive(Clone, Default)]
+pub enum CameraTarget {
+    /// The window or off-screen target the scene is rendered for.
+    #[default]
+    RenderTarget,
+    /// Rendered before the cameras of the render target, so their meshes and UI elements can
+    /// sample `RenderTexture::texture` in the same frame. Viewport and scissor are in texture
+    /// pixels, UI elements are not drawn. Cameras sharing a texture draw in camera order over
+    /// one clear of it.
+    Texture(Arc<RenderTexture>),
+}
+
+/// Axis a perspective field of view or an orthographic size is measured along, the other
+/// axis follows the aspect ratio of the viewport.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectionAxis {
+    #[default]
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// `fov` in degree.
+    Perspective { fov: f32, axis: ProjectionAxis },
+    /// `size` is the visible extent in world units, centered on the view direction.
+    Orthographic { size: f32, axis: ProjectionAxis },
+    /// Perspective with the edges of the view at the near plane, e.g. for stereo or tiled
+    /// rendering. Ignores the viewport's aspect ratio.
+    OffCenter {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+    /// Used as is, `z_near`, `z_far` and the viewport are ignored. Maps depth to 0..1 like
+    /// the other projections.
+    Matrix(Mat4),
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fov: 45.0,
+            axis: ProjectionAxis::Vertical,
+        }
+    }
+}
+
+/// Counts of the last frame the camera was recorded in. Keep a clone of `Camera::stats` to
+/// read them after rendering.
+#[derive(Debug, Default)]
+pub struct CameraStats {
+    visible_meshes: AtomicUsize,
+    culled_meshes: AtomicUsize,
+    draw_calls: AtomicUsize,
+}
+
+impl CameraStats {
+    pub fn visible_meshes(&self) -> usize {
+        self.visible_meshes.load(Ordering::Relaxed)
+    }
+    /// Meshes outside the view frustum, not recorded.
+    pub fn culled_meshes(&self) -> usize {
+        self.culled_meshes.load(Ordering::Relaxed)
+    }
+    /// Draws the visible meshes were merged into, including the instanced mesh renderers.
+    pub fn draw_calls(&self) -> usize {
+        self.draw_calls.load(Ordering::Relaxed)
+    }
+}
 
 pub struct Camera {
     pub view_matrix: Mat4,
     pub z_near: f32,
     pub z_far: f32,
-    pub fov: f32, // in degree
+    pub projection: Projection,
     pub viewport: Viewport,
     pub scissor: Rect2D,
     pub mesh_renderers: Vec<Arc<MeshRenderer>>,
+    pub instanced_mesh_renderers: Vec<Arc<InstancedMeshRenderer>>,
+    pub target: CameraTarget,
+    pub stats: Arc<CameraStats>,
 }
 
 impl Camera {
@@ -23,27 +104,140 @@ impl Camera {
             view_matrix: Default::default(),
             z_near: 0.1,
             z_far: 100.0,
-            fov: 45.0,
+            projection: Projection::default(),
             viewport: Default::default(),
             scissor: Default::default(),
             mesh_renderers: vec![],
+            instanced_mesh_renderers: vec![],
+            target: CameraTarget::RenderTarget,
+            stats: Default::default(),
         }
     }
-    pub(crate) fn get_and_order_meshes(&self) -> ParallelGroup<Arc<MeshRenderer>> {
-        // TODO order by distance
-        let mut parallel_group = ParallelGroup::new();
-        for mesh_renderer in &self.mesh_renderers {
-            parallel_group.push(mesh_renderer.clone())
+    /// A camera rendering into the whole texture.
+    pub fn with_render_texture(render_texture: Arc<RenderTexture>) -> Camera {
+        Camera {
+            viewport: render_texture.viewport(),
+            scissor: render_texture.scissor(),
+            target: CameraTarget::Texture(render_texture),
+            ..Camera::new()
+        }
+    }
+    pub(crate) fn render_texture(&self) -> Option<&Arc<RenderTexture>> {
+        match &self.target {
+            CameraTarget::RenderTarget => None,
+            CameraTarget::Texture(render_texture) => Some(render_texture),
+        }
+    }
+    /// Culls the meshes outside the view frustum and sorts the visible ones by render queue,
+    /// priority and view space depth of their bounds, both in parallel, then merges them into
+    /// instanced batches ordered with the instanced mesh renderers.
+    pub(crate) fn get_and_batch_meshes(&self) -> Vec<MeshDraw> {
+        let frustum =
+            Frustum::from_view_projection(&(self.get_projection_matrix() * self.view_matrix));
+        let mut visible_meshes: Vec<_> = self
+            .mesh_renderers
+            .par_iter()
+            .filter(|mesh_renderer| match &mesh_renderer.bounds {
+                Some(bounds) => bounds.is_visible(&mesh_renderer.model, &frustum),
+                None => true,
+            })
+            .map(|mesh_renderer| (self.view_depth(mesh_renderer), mesh_renderer))
+            .collect();
+        self.stats
+            .visible_meshes
+            .store(visible_meshes.len(), Ordering::Relaxed);
+        self.stats.culled_meshes.store(
+            self.mesh_renderers.len() - visible_meshes.len(),
+            Ordering::Relaxed,
+        );
+        visible_meshes.par_sort_by(|(depth_a, mesh_a), (depth_b, mesh_b)| {
+            let render_queue = mesh_a.render_queue();
+            render_queue
+                .cmp(&mesh_b.render_queue())
+                .then(mesh_a.priority.cmp(&mesh_b.priority))
+                .then_with(|| match render_queue {
+                    RenderQueue::Opaque => depth_a.total_cmp(depth_b),
+                    RenderQueue::Transparent => depth_b.total_cmp(depth_a),
+                })
+        });
+        let batches = MeshBatch::from_ordered(
+            visible_meshes
+                .into_iter()
+                .map(|(_, mesh_renderer)| mesh_renderer.clone())
+                .collect(),
+        );
+        let draws = MeshDraw::merge_ordered(batches, &self.instanced_mesh_renderers);
+        self.stats.draw_calls.store(draws.len(), Ordering::Relaxed);
+        draws
+    }
+    /// Distance along the view direction to the center of the mesh bounds, or to the mesh
+    /// origin without bounds.
+    fn view_depth(&self, mesh_renderer: &MeshRenderer) -> f32 {
+        let center = mesh_renderer
+            .bounds
+            .map_or(Vec3::ZERO, |bounds| bounds.center());
+        // the view looks along -z
+        -(self.view_matrix * mesh_renderer.model)
+            .transform_point3(center)
+            .z
+    }
+    /// Width divided by height of the viewport, 1 while it has no area.
+    fn aspect_ratio(&self) -> f32 {
+        if self.viewport.width > 0.0 && self.viewport.height > 0.0 {
+            self.viewport.width / self.viewport.height
+        } else {
+            1.0
         }
-        parallel_group
     }
     pub(crate) fn get_projection_matrix(&self) -> Mat4 {
-        Mat4::perspective_rh(
-            self.fov.to_radians(),
-            self.viewport.width / self.viewport.height,
-            self.z_near,
-            self.z_far,
-        )
+        let aspect_ratio = self.aspect_ratio();
+        match self.projection {
+            Projection::Perspective { fov, axis } => {
+                let fov_y = match axis {
+                    ProjectionAxis::Vertical => fov.to_radians(),
+                    ProjectionAxis::Horizontal => {
+                        2.0 * ((fov.to_radians() / 2.0).tan() / aspect_ratio).atan()
+                    }
+                };
+                Mat4::perspective_rh(fov_y, aspect_ratio, self.z_near, self.z_far)
+            }
+            Projection::Orthographic { size, axis } => {
+                let (width, height) = match axis {
+                    ProjectionAxis::Vertical => (size * aspect_ratio, size),
+                    ProjectionAxis::Horizontal => (size, size / aspect_ratio),
+                };
+                Mat4::orthographic_rh(
+                    -width / 2.0,
+                    width / 2.0,
+                    -height / 2.0,
+                    height / 2.0,
+                    self.z_near,
+                    self.z_far,
+                )
+            }
+            Projection::OffCenter {
+                left,
+                right,
+                bottom,
+                top,
+            } => {
+                // same conventions as `Mat4::perspective_rh`
+                let z_near = self.z_near;
+                let depth = self.z_far / (z_near - self.z_far);
+                Mat4::from_cols(
+                    Vec4::new(2.0 * z_near / (right - left), 0.0, 0.0, 0.0),
+                    Vec4::new(0.0, 2.0 * z_near / (top - bottom), 0.0, 0.0),
+                    Vec4::new(
+                        (right + left) / (right - left),
+                        (top + bottom) / (top - bottom),
+                        depth,
+                        -1.0,
+                    ),
+                    Vec4::new(0.0, 0.0, depth * z_near, 0.0),
+                )
+            }
+            Projection::Matrix(matrix) => matrix,
+        }
     }
 }
 
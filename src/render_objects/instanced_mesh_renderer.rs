@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2, Vec4};
+use parking_lot::Mutex;
+use tyleri_gpu_utils::memory::variable_length_buffer::VariableLengthBuffer;
+use yarvk::command::command_buffer::CommandBuffer;
+use yarvk::command::command_buffer::Level::SECONDARY;
+use yarvk::command::command_buffer::RenderPassScope::INSIDE;
+use yarvk::command::command_buffer::State::RECORDING;
+use yarvk::device::Device;
+use yarvk::physical_device::memory_properties::MemoryType;
+use yarvk::pipeline::Pipeline;
+use yarvk::{BufferUsageFlags, PipelineBindPoint};
+
+use crate::render_device::RenderDevice;
+use crate::render_objects::mesh_renderer::{BlendMode, RenderQueue, StencilState};
+use crate::resource::{StaticIndices, StaticTexture, StaticVertices};
+
+/// Per-instance vertex input of an `InstancedMeshRenderer`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceData {
+    pub model: Mat4,
+    /// Multiplied with the texture color, sRGB encoded like the texture.
+    pub color: Vec4,
+    /// Added to the uv of the mesh, e.g. to pick a cell of a texture atlas.
+    pub uv_offset: Vec2,
+}
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        Self {
+            model: Mat4::IDENTITY,
+            color: Vec4::ONE,
+            uv_offset: Vec2::ZERO,
+        }
+    }
+}
+
+/// Host visible instances, rewritten by the application whenever they change. Frames in
+/// flight keep reading the instances they were recorded with.
+pub struct InstanceBuffer {
+    device: Arc<Device>,
+    memory_type: MemoryType,
+    usage: BufferUsageFlags,
+    /// The first buffer holds the latest instances, the others are reused once no frame
+    /// holds them anymore.
+    buffers: Mutex<Vec<Arc<VariableLengthBuffer<InstanceData>>>>,
+}
+
+impl InstanceBuffer {
+    pub fn len(&self) -> usize {
+        self.buffers.lock()[0].len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Replaces the instances drawn by the next recorded frames.
+    pub fn write(&self, instances: &[InstanceData]) {
+        let mut buffers = self.buffers.lock();
+        let index = match buffers
+            .iter_mut()
+            .position(|buffer| Arc::get_mut(buffer).is_some())
+        {
+            Some(index) => index,
+            None => {
+                buffers.push(Arc::new(VariableLengthBuffer::new(
+                    &self.device,
+                    &self.memory_type,
+                    self.usage,
+                    instances.len().max(1),
+                )));
+                buffers.len() - 1
+            }
+        };
+        let buffer = Arc::get_mut(&mut buffers[index])
+            .expect("internal error: instance buffer is holding by others");
+        buffer.clear();
+        buffer.expand_to(instances.len());
+        buffer.write(instances);
+        buffers.swap(0, index);
+    }
+    pub(crate) fn current(&self) -> Arc<VariableLengthBuffer<InstanceData>> {
+        self.buffers.lock()[0].clone()
+    }
+}
+
+/// Draws a mesh once per instance of `instances` in a single call, e.g. for particles or
+/// grass. Not culled, drawn after the `MeshRenderer`s of the same queue and priority.
+pub struct InstancedMeshRenderer {
+    pub vertices: StaticVertices,
+    pub indices: StaticIndices,
+    pub descriptor_set: StaticTexture,
+    pub instances: Arc<InstanceBuffer>,
+    pub stencil: Option<StencilState>,
+    pub blend_mode: BlendMode,
+    /// Overrides the queue the mesh is drawn in, the one of `blend_mode` by default.
+    pub render_queue: Option<RenderQueue>,
+    pub priority: i32,
+}
+
+impl InstancedMeshRenderer {
+    pub fn new(
+        vertices: StaticVertices,
+        indices: StaticIndices,
+        descriptor_set: StaticTexture,
+        instances: Arc<InstanceBuffer>,
+    ) -> Self {
+        Self {
+            vertices,
+            indices,
+            descriptor_set,
+            instances,
+            stencil: None,
+            blend_mode: BlendMode::Opaque,
+            render_queue: None,
+            priority: 0,
+        }
+    }
+    pub(crate) fn render_queue(&self) -> RenderQueue {
+        self.render_queue
+            .unwrap_or_else(|| self.blend_mode.render_queue())
+    }
+    /// Binds the instances to binding 1, replacing the instances of batched meshes.
+    pub fn renderer_instances(
+        &self,
+        pipeline: &Arc<Pipeline>,
+        command_buffer: &mut CommandBuffer<{ SECONDARY }, { RECORDING }, { INSIDE }>,
+    ) {
+        let instances = self.instances.current();
+        let instance_count = instances.len() as u32;
+        if instance_count == 0 {
+            return;
+        }
+        command_buffer.cmd_bind_vertex_buffers(1, [instances as _], &[0]);
+        command_buffer.cmd_bind_descriptor_sets(
+            PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout.clone(),
+            0,
+            [self.descriptor_set.clone() as _],
+            &[],
+        );
+        command_buffer.cmd_draw_indexed(
+            self.indices.len as u32,
+            instance_count,
+            self.indices.offset as _,
+            self.vertices.offset as _,
+            0,
+        );
+    }
+}
+
+impl RenderDevice {
+    /// Creates an empty instance buffer with room for `capacity` instances, it grows on
+    /// `InstanceBuffer::write`.
+    pub fn create_instance_buffer(&self, capacity: usize) -> Arc<InstanceBuffer> {
+        let instances_info = &self.memory_allocator.resource_infos.instances_info;
+        let buffer = VariableLengthBuffer::new(
+            &self.device,
+            &instances_info.memory_type,
+            instances_info.usage,
+            capacity.max(1),
+        );
+        Arc::new(InstanceBuffer {
+            device: self.device.clone(),
+            memory_type: instances_info.memory_type.clone(),
+            usage: instances_info.usage,
+            buffers: Mutex::new(vec![Arc::new(buffer)]),
+        })
+    }
+}
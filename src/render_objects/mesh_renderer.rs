@@ -1,9 +1,10 @@
+use std::hash::{Hash, Hasher};
 use std::mem::size_of;
 use std::slice::from_raw_parts;
 use std::sync::Arc;
 
 use glam::Mat4;
-use tyleri_api::data_structure::vertices::Vertex;
+use rustc_hash::FxHashMap;
 use tyleri_gpu_utils::descriptor::single_image_descriptor_set_layout::SingleImageDescriptorValue;
 use tyleri_gpu_utils::memory::block_based_memory::bindless_buffer::BindlessBuffer;
 use yarvk::command::command_buffer::CommandBuffer;
@@ -13,55 +14,229 @@ use yarvk::command::command_buffer::State::RECORDING;
 use yarvk::descriptor_set::descriptor_set::DescriptorSet;
 use yarvk::pipeline::shader_stage::ShaderStage;
 use yarvk::pipeline::Pipeline;
-use yarvk::PipelineBindPoint;
+use yarvk::{CompareOp, PipelineBindPoint, StencilOp, StencilOpState};
+
+use crate::render_objects::bounding_volume::BoundingVolume;
+use crate::render_objects::instanced_mesh_renderer::InstancedMeshRenderer;
+use crate::resource::StaticVertices;
 
 #[repr(C)]
-struct MVP {
-    view_x_model: Mat4,
+struct ViewProjection {
+    view: Mat4,
     projection: Mat4,
 }
 
+/// Per-instance vertex input of the mesh pipelines.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct MeshInstance {
+    pub(crate) model: Mat4,
+}
+
+/// Stencil test and write of a mesh, ignored unless the depth image format has a stencil
+/// component. Each distinct state builds its own pipeline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct StencilState {
+    /// Compares `reference & compare_mask` with `stored value & compare_mask`.
+    pub compare_op: CompareOp,
+    pub pass_op: StencilOp,
+    pub fail_op: StencilOp,
+    pub depth_fail_op: StencilOp,
+    pub reference: u32,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+}
+
+impl StencilState {
+    /// Always passes and writes `reference`, e.g. marks the pixels of an outlined mesh.
+    pub fn write(reference: u32) -> Self {
+        Self {
+            compare_op: CompareOp::ALWAYS,
+            pass_op: StencilOp::REPLACE,
+            fail_op: StencilOp::KEEP,
+            depth_fail_op: StencilOp::KEEP,
+            reference,
+            compare_mask: u32::MAX,
+            write_mask: u32::MAX,
+        }
+    }
+    /// Draws where `reference <compare_op> stored value` holds without changing the stencil,
+    /// e.g. `NOT_EQUAL` for outlines or `EQUAL` for masking.
+    pub fn test(compare_op: CompareOp, reference: u32) -> Self {
+        Self {
+            compare_op,
+            pass_op: StencilOp::KEEP,
+            fail_op: StencilOp::KEEP,
+            depth_fail_op: StencilOp::KEEP,
+            reference,
+            compare_mask: u32::MAX,
+            write_mask: 0,
+        }
+    }
+    pub(crate) fn op_state(&self) -> StencilOpState {
+        StencilOpState {
+            fail_op: self.fail_op,
+            pass_op: self.pass_op,
+            depth_fail_op: self.depth_fail_op,
+            compare_op: self.compare_op,
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+        }
+    }
+}
+
+/// How the fragments of a mesh are combined with the color attachment. Each distinct mode
+/// builds its own pipeline.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    /// Discards fragments with a texture alpha below `cutoff`, e.g. for foliage cards. Drawn
+    /// and written to depth like opaque meshes.
+    AlphaTest { cutoff: f32 },
+    /// Blends by the texture alpha, e.g. for glass and fading objects.
+    AlphaBlend,
+    /// Adds the color weighted by its alpha, e.g. for fire and glows.
+    Additive,
+    /// Blends colors already multiplied by their alpha.
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Blended meshes do not write depth and are drawn after opaque meshes.
+    pub fn is_blended(&self) -> bool {
+        matches!(
+            self,
+            BlendMode::AlphaBlend | BlendMode::Additive | BlendMode::Premultiplied
+        )
+    }
+    pub fn render_queue(&self) -> RenderQueue {
+        if self.is_blended() {
+            RenderQueue::Transparent
+        } else {
+            RenderQueue::Opaque
+        }
+    }
+    /// The cutoff compared by bits, with every zero and every NaN equal to each other.
+    fn cutoff_bits(&self) -> Option<u32> {
+        match self {
+            BlendMode::AlphaTest { cutoff } if cutoff.is_nan() => Some(f32::NAN.to_bits()),
+            BlendMode::AlphaTest { cutoff } => Some((cutoff + 0.0).to_bits()),
+            _ => None,
+        }
+    }
+}
+
+// pipelines are keyed by blend mode, so equality has to agree with the hash
+impl PartialEq for BlendMode {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.cutoff_bits() == other.cutoff_bits()
+    }
+}
+
+impl Eq for BlendMode {}
+
+impl Hash for BlendMode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        self.cutoff_bits().hash(state);
+    }
+}
+
+/// Meshes of a camera are drawn queue by queue.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum RenderQueue {
+    /// Sorted front to back to reject hidden pixels early by the depth test.
+    #[default]
+    Opaque,
+    /// Drawn after every opaque mesh, sorted back to front.
+    Transparent,
+}
+
 pub struct MeshRenderer {
     // TODO maybe split vertices to three buffers?
-    pub vertices: Arc<BindlessBuffer<Vertex>>,
+    pub vertices: StaticVertices,
     pub indices: Arc<BindlessBuffer<u32>>,
     pub descriptor_set: Arc<DescriptorSet<SingleImageDescriptorValue>>,
     pub model: Mat4,
+    /// Give a mesh writing the stencil a lower `priority` than the meshes testing it, else
+    /// they are ordered by distance only.
+    pub stencil: Option<StencilState>,
+    /// The bounds of `vertices` by default, meshes without bounds are never culled.
+    pub bounds: Option<BoundingVolume>,
+    pub blend_mode: BlendMode,
+    /// Overrides the queue the mesh is drawn in, the one of `blend_mode` by default.
+    pub render_queue: Option<RenderQueue>,
+    /// Meshes of a queue are drawn by ascending priority, the distance to the camera only
+    /// orders meshes of the same priority.
+    pub priority: i32,
 }
 
 impl MeshRenderer {
     pub fn new(
-        vertices: Arc<BindlessBuffer<Vertex>>,
+        vertices: StaticVertices,
         indices: Arc<BindlessBuffer<u32>>,
         descriptor_set: Arc<DescriptorSet<SingleImageDescriptorValue>>,
     ) -> Self {
+        let bounds = vertices.bounds();
         Self {
             vertices,
             indices,
             descriptor_set,
             model: Default::default(),
+            stencil: None,
+            bounds,
+            blend_mode: BlendMode::Opaque,
+            render_queue: None,
+            priority: 0,
         }
     }
-    pub fn renderer_mesh(
-        &self,
+    pub(crate) fn render_queue(&self) -> RenderQueue {
+        self.render_queue
+            .unwrap_or_else(|| self.blend_mode.render_queue())
+    }
+    /// Meshes sharing the buffers, texture and pipeline state can be drawn instanced.
+    fn can_batch(&self, other: &MeshRenderer) -> bool {
+        Arc::ptr_eq(&self.vertices, &other.vertices)
+            && Arc::ptr_eq(&self.indices, &other.indices)
+            && Arc::ptr_eq(&self.descriptor_set, &other.descriptor_set)
+            && self.stencil == other.stencil
+            && self.blend_mode == other.blend_mode
+            && self.render_queue() == other.render_queue()
+            && self.priority == other.priority
+    }
+    pub(crate) fn push_view_projection(
         pipeline: &Arc<Pipeline>,
         view: &Mat4,
         projection: &Mat4,
         command_buffer: &mut CommandBuffer<{ SECONDARY }, { RECORDING }, { INSIDE }>,
     ) {
-        let view_x_model = *view * self.model;
-        let mvp = MVP {
-            view_x_model,
-            projection: projection.clone(),
+        let view_projection = ViewProjection {
+            view: *view,
+            projection: *projection,
+        };
+        let push_constant = unsafe {
+            from_raw_parts(
+                &view_projection as *const ViewProjection as *const u8,
+                size_of::<ViewProjection>(),
+            )
         };
-        let push_constant =
-            unsafe { from_raw_parts(&mvp as *const MVP as *const u8, size_of::<MVP>()) };
         command_buffer.cmd_push_constants(
             &pipeline.pipeline_layout,
             &ShaderStage::Vertex,
             0,
             push_constant,
         );
+    }
+    pub fn renderer_mesh(
+        &self,
+        pipeline: &Arc<Pipeline>,
+        first_instance: u32,
+        instance_count: u32,
+        command_buffer: &mut CommandBuffer<{ SECONDARY }, { RECORDING }, { INSIDE }>,
+    ) {
         command_buffer.cmd_bind_descriptor_sets(
             PipelineBindPoint::GRAPHICS,
             pipeline.pipeline_layout.clone(),
@@ -71,10 +246,148 @@ impl MeshRenderer {
         );
         command_buffer.cmd_draw_indexed(
             self.indices.len as u32,
-            1,
+            instance_count,
             self.indices.offset as _,
             self.vertices.offset as _,
-            1,
+            first_instance,
         );
     }
 }
+
+/// A draw of a camera in recording order.
+pub(crate) enum MeshDraw {
+    Batch(MeshBatch),
+    Instanced(Arc<InstancedMeshRenderer>),
+}
+
+impl MeshDraw {
+    fn order_key(&self) -> (RenderQueue, i32) {
+        match self {
+            MeshDraw::Batch(batch) => (
+                batch.mesh_renderer.render_queue(),
+                batch.mesh_renderer.priority,
+            ),
+            MeshDraw::Instanced(instanced) => (instanced.render_queue(), instanced.priority),
+        }
+    }
+    /// Appends the instanced renderers after the ordered batches of the same queue and
+    /// priority.
+    pub(crate) fn merge_ordered(
+        batches: Vec<MeshBatch>,
+        instanced_mesh_renderers: &[Arc<InstancedMeshRenderer>],
+    ) -> Vec<MeshDraw> {
+        let mut draws: Vec<_> = batches
+            .into_iter()
+            .map(MeshDraw::Batch)
+            .chain(
+                instanced_mesh_renderers
+                    .iter()
+                    .cloned()
+                    .map(MeshDraw::Instanced),
+            )
+            .collect();
+        // stable, the batches keep their order
+        draws.sort_by_key(MeshDraw::order_key);
+        draws
+    }
+}
+
+/// Visible meshes of a camera drawn in one instanced call, `mesh_renderer` is the first of
+/// them.
+pub(crate) struct MeshBatch {
+    pub(crate) mesh_renderer: Arc<MeshRenderer>,
+    pub(crate) instances: Vec<MeshInstance>,
+    /// Offset of `instances` in the instance buffer of the frame.
+    pub(crate) first_instance: u32,
+}
+
+impl MeshBatch {
+    /// Merges meshes ordered by `Camera::get_and_batch_meshes` into batches. Opaque meshes
+    /// join the first batch they fit, so state changes are minimized at the cost of a
+    /// coarser front to back order. Transparent meshes only join the previous batch to keep
+    /// the back to front order.
+    pub(crate) fn from_ordered(mesh_renderers: Vec<Arc<MeshRenderer>>) -> Vec<MeshBatch> {
+        let mut batches: Vec<MeshBatch> = Vec::new();
+        // (vertices, indices, descriptor set) pointers to the opaque batches sharing them
+        let mut opaque_batches: FxHashMap<(usize, usize, usize), Vec<usize>> = FxHashMap::default();
+        for mesh_renderer in mesh_renderers {
+            let instance = MeshInstance {
+                model: mesh_renderer.model,
+            };
+            let batch_index = match mesh_renderer.render_queue() {
+                RenderQueue::Opaque => opaque_batches
+                    .get(&Self::buffers_key(&mesh_renderer))
+                    .and_then(|indices| {
+                        indices
+                            .iter()
+                            .copied()
+                            .find(|index| batches[*index].mesh_renderer.can_batch(&mesh_renderer))
+                    }),
+                RenderQueue::Transparent => batches
+                    .last()
+                    .filter(|batch| batch.mesh_renderer.can_batch(&mesh_renderer))
+                    .map(|_| batches.len() - 1),
+            };
+            match batch_index {
+                Some(batch_index) => batches[batch_index].instances.push(instance),
+                None => {
+                    if mesh_renderer.render_queue() == RenderQueue::Opaque {
+                        opaque_batches
+                            .entry(Self::buffers_key(&mesh_renderer))
+                            .or_default()
+                            .push(batches.len());
+                    }
+                    batches.push(MeshBatch {
+                        mesh_renderer,
+                        instances: vec![instance],
+                        first_instance: 0,
+                    });
+                }
+            }
+        }
+        batches
+    }
+    fn buffers_key(mesh_renderer: &MeshRenderer) -> (usize, usize, usize) {
+        (
+            Arc::as_ptr(&mesh_renderer.vertices) as usize,
+            Arc::as_ptr(&mesh_renderer.indices) as usize,
+            Arc::as_ptr(&mesh_renderer.descriptor_set) as usize,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    fn hash(blend_mode: BlendMode) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        blend_mode.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn alpha_test(cutoff: f32) -> BlendMode {
+        BlendMode::AlphaTest { cutoff }
+    }
+
+    #[test]
+    fn equal_cutoffs_hash_equal() {
+        for (a, b) in [
+            (alpha_test(0.5), alpha_test(0.5)),
+            (alpha_test(0.0), alpha_test(-0.0)),
+            (alpha_test(f32::NAN), alpha_test(-f32::NAN)),
+        ] {
+            assert_eq!(a, b);
+            assert_eq!(hash(a), hash(b));
+        }
+    }
+
+    #[test]
+    fn distinct_modes_are_not_equal() {
+        assert_ne!(alpha_test(0.5), alpha_test(0.25));
+        assert_ne!(alpha_test(0.0), BlendMode::Opaque);
+        assert_ne!(BlendMode::AlphaBlend, BlendMode::Additive);
+    }
+}
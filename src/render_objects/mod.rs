@@ -1,4 +1,6 @@
+pub mod bounding_volume;
 pub mod camera;
+pub mod instanced_mesh_renderer;
 pub mod mesh_renderer;
 pub mod ui;
 
@@ -15,6 +17,19 @@ impl<T> ParallelGroup<T> {
             .collect();
         Self { groups, cursor: 0 }
     }
+    /// Splits `ts` into contiguous groups of even size. Groups are recorded into command
+    /// buffers executed in thread order, so the order of `ts` is kept.
+    pub fn from_ordered(ts: Vec<T>) -> Self {
+        let threads = rayon::current_num_threads();
+        let group_len = ((ts.len() + threads - 1) / threads).max(1);
+        let mut groups: Vec<_> = (0..threads)
+            .map(|_| Vec::with_capacity(group_len))
+            .collect();
+        for (index, t) in ts.into_iter().enumerate() {
+            groups[index / group_len].push(t);
+        }
+        Self { groups, cursor: 0 }
+    }
     pub fn push(&mut self, t: T) {
         self.groups[self.cursor].push(t);
         self.cursor = (self.cursor + 1) % self.groups.len();
@@ -15,10 +15,13 @@ use yarvk::semaphore::Semaphore;
 
 use crate::render_device::RenderDevice;
 use crate::render_objects::camera::Camera;
+use crate::render_objects::mesh_renderer::MeshInstance;
 use crate::render_objects::ui::UIElement;
+use crate::render_target::frame_capture::FrameCapture;
 
 const DEFAULT_VERTICES_BUFFER_LEN: usize = 2 * 1024;
 const DEFAULT_INDICES_BUFFER_LEN: usize = 1024;
+const DEFAULT_INSTANCES_BUFFER_LEN: usize = 1024;
 
 pub(crate) struct PresentResources {
     pub(crate) present_complete_semaphore: Semaphore,
@@ -64,8 +67,11 @@ impl RecordResources {
 pub struct RenderResources {
     pub(crate) ui_vertices: Arc<VariableLengthBuffer<UIVertex>>,
     pub(crate) ui_indices: Arc<VariableLengthBuffer<u32>>,
+    /// Written with the batched meshes of every camera while recording.
+    pub(crate) mesh_instances: Arc<VariableLengthBuffer<MeshInstance>>,
     pub(crate) cameras: Vec<Camera>,
     pub(crate) ui: Vec<UIElement>,
+    pub(crate) frame_capture: Option<FrameCapture>,
 }
 
 impl RenderResources {
@@ -98,11 +104,27 @@ impl RenderResources {
                 .usage,
             DEFAULT_INDICES_BUFFER_LEN,
         ));
+        let mesh_instances = Arc::new(VariableLengthBuffer::new(
+            &render_device.device,
+            &render_device
+                .memory_allocator
+                .resource_infos
+                .instances_info
+                .memory_type,
+            render_device
+                .memory_allocator
+                .resource_infos
+                .instances_info
+                .usage,
+            DEFAULT_INSTANCES_BUFFER_LEN,
+        ));
         Self {
             ui_vertices,
             ui_indices: ui_dices,
+            mesh_instances,
             cameras: vec![],
             ui: Default::default(),
+            frame_capture: None,
         }
     }
     pub(crate) fn clear(&mut self) {
@@ -112,7 +134,11 @@ impl RenderResources {
         let ui_vertices = Arc::get_mut(&mut self.ui_vertices)
             .expect("internal error: vertex buffer is holding by others");
         ui_vertices.clear();
+        let mesh_instances = Arc::get_mut(&mut self.mesh_instances)
+            .expect("internal error: instance buffer is holding by others");
+        mesh_instances.clear();
         self.cameras.clear();
+        self.frame_capture = None;
     }
 }
 
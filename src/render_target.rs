@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use yarvk::image_view::ImageView;
+use yarvk::{ColorSpaceKHR, Extent2D, Format, ImageLayout};
+
+use crate::render_target::hdr::HdrConfig;
+use crate::render_window::ImageHandle;
+
+pub mod frame_capture;
+pub mod hdr;
+pub mod offscreen_target;
+pub mod render_texture;
+
+/// Images a `RenderingFunction` renders into, either a swapchain or an off-screen target.
+pub trait RenderTarget {
+    fn format(&self) -> Format;
+    fn extent(&self) -> Extent2D;
+    /// Layout the color images are left in at the end of the render pass.
+    fn final_layout(&self) -> ImageLayout;
+    fn create_color_image_views(&self) -> Vec<(ImageHandle, Arc<ImageView>)>;
+    fn color_space(&self) -> ColorSpaceKHR {
+        ColorSpaceKHR::SRGB_NONLINEAR
+    }
+    /// Renders into a floating-point intermediate image and tone maps it into the target.
+    fn hdr(&self) -> Option<&HdrConfig> {
+        None
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use tyleri_gpu_utils::memory::array_device_memory::ArrayDeviceMemory;
+use tyleri_gpu_utils::memory::{try_memory_type, IMemBakBuf};
+use yarvk::barrier::ImageMemoryBarrier;
+use yarvk::command::command_buffer::CommandBuffer;
+use yarvk::command::command_buffer::Level::PRIMARY;
+use yarvk::command::command_buffer::RenderPassScope::OUTSIDE;
+use yarvk::command::command_buffer::State::RECORDING;
+use yarvk::device_memory::IMemoryRequirements;
+use yarvk::image_subresource_range::ImageSubresourceRange;
+use yarvk::image_view::ImageView;
+use yarvk::physical_device::SharingMode;
+use yarvk::pipeline::pipeline_stage_flags::PipelineStageFlag;
+use yarvk::{
+    AccessFlags, BufferImageCopy, BufferUsageFlags, ContinuousBuffer, DependencyFlags, Extent2D,
+    Format, ImageAspectFlags, ImageLayout, ImageSubresourceLayers, MemoryPropertyFlags, Offset3D,
+};
+
+use crate::render_device::RenderDevice;
+use crate::RendererError;
+
+/// Receives the pixels of a frame requested by `capture_frame`, once the GPU finished it.
+pub type FrameCaptureReceiver = Receiver<CapturedFrame>;
+
+/// A rendered frame copied back to the host, tightly packed RGBA8 rows.
+pub struct CapturedFrame {
+    pub extent: Extent2D,
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = (y as usize * self.extent.width as usize + x as usize) * 4;
+        [
+            self.pixels[offset],
+            self.pixels[offset + 1],
+            self.pixels[offset + 2],
+            self.pixels[offset + 3],
+        ]
+    }
+    #[cfg(feature = "png")]
+    pub fn write_png<W: std::io::Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.extent.width, self.extent.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)
+    }
+    #[cfg(feature = "png")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        let file = std::fs::File::create(path)?;
+        self.write_png(std::io::BufWriter::new(file))
+    }
+}
+
+pub(crate) struct FrameCapture {
+    buffer: Arc<IMemBakBuf>,
+    extent: Extent2D,
+    format: Format,
+    sender: Sender<CapturedFrame>,
+}
+
+impl FrameCapture {
+    /// Fails with `RendererError::UnsupportedFeature` for formats other than RGBA8 and BGRA8.
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        extent: Extent2D,
+        format: Format,
+    ) -> Result<(Self, FrameCaptureReceiver), RendererError> {
+        if !Self::is_supported_format(format) {
+            return Err(RendererError::UnsupportedFeature(format!(
+                "capturing {format:?} images"
+            )));
+        }
+        let device = &render_device.device;
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        let mut buffer_builder = ContinuousBuffer::builder(device);
+        buffer_builder.sharing_mode(SharingMode::EXCLUSIVE);
+        buffer_builder.size(size);
+        buffer_builder.usage(BufferUsageFlags::TRANSFER_DST);
+        let buffer = buffer_builder.build()?;
+        let memory_requirement = buffer.get_memory_requirements();
+        let mut buffers = try_memory_type(
+            memory_requirement,
+            device.physical_device.memory_properties(),
+            Some(MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT),
+            memory_requirement.size,
+            |memory_type| {
+                ArrayDeviceMemory::new_resources(&device, &buffer_builder, 1, &memory_type).ok()
+            },
+        )
+        .ok_or(RendererError::Vulkan(
+            yarvk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+        ))?;
+        let (sender, receiver) = channel();
+        let frame_capture = Self {
+            buffer: buffers
+                .pop()
+                .expect("internal error: no capture buffer created"),
+            extent,
+            format,
+            sender,
+        };
+        Ok((frame_capture, receiver))
+    }
+    fn is_supported_format(format: Format) -> bool {
+        matches!(
+            format,
+            Format::R8G8B8A8_UNORM
+                | Format::R8G8B8A8_SRGB
+                | Format::B8G8R8A8_UNORM
+                | Format::B8G8R8A8_SRGB
+        )
+    }
+    /// Copies `image_view` into the capture buffer, must be recorded after the render pass
+    /// left the image in `layout`. The image is returned to `layout` afterwards.
+    pub(crate) fn record(
+        &self,
+        image_view: &Arc<ImageView>,
+        layout: ImageLayout,
+        command_buffer: &mut CommandBuffer<{ PRIMARY }, { RECORDING }, { OUTSIDE }>,
+    ) {
+        let subresource_range = ImageSubresourceRange::builder()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1)
+            .build();
+        command_buffer.cmd_pipeline_barrier(
+            PipelineStageFlag::ColorAttachmentOutput.into(),
+            PipelineStageFlag::Transfer.into(),
+            DependencyFlags::empty(),
+            [],
+            [],
+            [ImageMemoryBarrier::builder(image_view.image.clone())
+                .src_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(AccessFlags::TRANSFER_READ)
+                .old_layout(layout)
+                .new_layout(ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .subresource_range(subresource_range)
+                .build()],
+        );
+        command_buffer.cmd_copy_image_to_buffer(
+            image_view.image.clone(),
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.buffer.clone(),
+            &[BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: ImageSubresourceLayers::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .layer_count(1)
+                    .build(),
+                image_offset: Offset3D::default(),
+                image_extent: self.extent.into(),
+            }],
+        );
+        if layout != ImageLayout::TRANSFER_SRC_OPTIMAL {
+            command_buffer.cmd_pipeline_barrier(
+                PipelineStageFlag::Transfer.into(),
+                PipelineStageFlag::BottomOfPipe.into(),
+                DependencyFlags::empty(),
+                [],
+                [],
+                [ImageMemoryBarrier::builder(image_view.image.clone())
+                    .src_access_mask(AccessFlags::TRANSFER_READ)
+                    .dst_access_mask(AccessFlags::empty())
+                    .old_layout(ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(layout)
+                    .subresource_range(subresource_range)
+                    .build()],
+            );
+        }
+    }
+    /// Reads the buffer back and sends it to the receiver, the submission must be finished.
+    /// The receiver is disconnected instead if the buffer cannot be mapped.
+    pub(crate) fn finish(self) {
+        let mut pixels = vec![0u8; self.extent.width as usize * self.extent.height as usize * 4];
+        if self
+            .buffer
+            .map_memory(|data| pixels.copy_from_slice(&data[..pixels.len()]))
+            .is_err()
+        {
+            return;
+        }
+        if matches!(self.format, Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB) {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+        // the receiver may have been dropped, nobody is waiting for this frame then
+        let _ = self.sender.send(CapturedFrame {
+            extent: self.extent,
+            pixels,
+        });
+    }
+}
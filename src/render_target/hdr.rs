@@ -0,0 +1,76 @@
+use yarvk::{ColorSpaceKHR, Format};
+
+/// Format of the intermediate image scenes are rendered into before tone mapping.
+pub const HDR_INTERMEDIATE_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HdrOutput {
+    /// `HDR10_ST2084` color space, PQ encoded Rec.2020.
+    Hdr10,
+    /// `EXTENDED_SRGB_LINEAR` color space, linear Rec.709 where 1.0 is 80 nits.
+    ScRgb,
+}
+
+impl HdrOutput {
+    pub(crate) fn color_space(&self) -> ColorSpaceKHR {
+        match self {
+            HdrOutput::Hdr10 => ColorSpaceKHR::HDR10_ST2084_EXT,
+            HdrOutput::ScRgb => ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        }
+    }
+    pub(crate) fn formats(&self) -> &'static [Format] {
+        match self {
+            HdrOutput::Hdr10 => &[
+                Format::A2B10G10R10_UNORM_PACK32,
+                Format::A2R10G10B10_UNORM_PACK32,
+            ],
+            HdrOutput::ScRgb => &[Format::R16G16B16A16_SFLOAT],
+        }
+    }
+}
+
+/// How the tone mapping pass encodes the intermediate image, decided by the color space of
+/// the target.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ToneMapping {
+    /// ACES filmic curve to SDR, used when the surface offers no HDR color space.
+    AcesSdr = 0,
+    /// SMPTE ST 2084 perceptual quantizer for HDR10.
+    Pq = 1,
+    /// Linear scRGB.
+    ScRgbLinear = 2,
+}
+
+impl ToneMapping {
+    pub fn for_color_space(color_space: ColorSpaceKHR) -> Self {
+        match color_space {
+            ColorSpaceKHR::HDR10_ST2084_EXT => ToneMapping::Pq,
+            ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => ToneMapping::ScRgbLinear,
+            _ => ToneMapping::AcesSdr,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HdrConfig {
+    /// HDR outputs in order of preference. If the surface supports none of them the scene is
+    /// tone mapped to SDR.
+    pub outputs: Vec<HdrOutput>,
+    /// Multiplier applied to the scene before tone mapping.
+    pub exposure: f32,
+    /// Luminance of scene value 1.0 on HDR displays.
+    pub paper_white_nits: f32,
+    /// Peak luminance the output is clamped to on HDR displays.
+    pub max_luminance_nits: f32,
+}
+
+impl Default for HdrConfig {
+    fn default() -> Self {
+        Self {
+            outputs: vec![HdrOutput::Hdr10, HdrOutput::ScRgb],
+            exposure: 1.0,
+            paper_white_nits: 200.0,
+            max_luminance_nits: 1000.0,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use tyleri_gpu_utils::memory::array_device_memory::ArrayDeviceMemory;
+use tyleri_gpu_utils::memory::{try_memory_type, IMemBakImg};
+use yarvk::device_memory::IMemoryRequirements;
+use yarvk::image_subresource_range::ImageSubresourceRange;
+use yarvk::image_view::{ImageView, ImageViewType};
+use yarvk::physical_device::SharingMode;
+use yarvk::queue::submit_info::{SubmitInfo, Submittable};
+use yarvk::{
+    ComponentMapping, ComponentSwizzle, ContinuousImage, Extent2D, Format, Handle,
+    ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, SampleCountFlags,
+};
+
+use crate::render_device::RenderDevice;
+use crate::render_scene::{RecordResources, RenderScene};
+use crate::render_target::frame_capture::{FrameCapture, FrameCaptureReceiver};
+use crate::render_target::RenderTarget;
+use crate::render_window::ImageHandle;
+use crate::rendering_function::RenderingFunction;
+use crate::RendererError;
+
+pub const OFFSCREEN_IMAGE_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+struct OffscreenImage {
+    image: Arc<IMemBakImg>,
+    format: Format,
+    extent: Extent2D,
+}
+
+impl RenderTarget for OffscreenImage {
+    fn format(&self) -> Format {
+        self.format
+    }
+    fn extent(&self) -> Extent2D {
+        self.extent
+    }
+    fn final_layout(&self) -> ImageLayout {
+        ImageLayout::TRANSFER_SRC_OPTIMAL
+    }
+    fn create_color_image_views(&self) -> Vec<(ImageHandle, Arc<ImageView>)> {
+        let image_view = ImageView::builder(self.image.clone())
+            .view_type(ImageViewType::Type2d)
+            .format(self.format)
+            .components(ComponentMapping {
+                r: ComponentSwizzle::R,
+                g: ComponentSwizzle::G,
+                b: ComponentSwizzle::B,
+                a: ComponentSwizzle::A,
+            })
+            .subresource_range(
+                ImageSubresourceRange::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(1)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        vec![(self.image.handle(), image_view)]
+    }
+}
+
+/// Renders a `RenderScene` into a color image owned by the renderer, no window or
+/// swapchain involved. Works with devices built by `RenderDeviceBuilder::headless`.
+pub struct OffscreenTarget<T: RenderingFunction> {
+    target: OffscreenImage,
+    render_scene: Option<RenderScene>,
+    rendering_function: T,
+}
+
+impl<T: RenderingFunction> OffscreenTarget<T> {
+    pub fn new(render_device: &RenderDevice, resolution: &Extent2D) -> Result<Self, RendererError> {
+        let image = Self::create_color_image(render_device, *resolution).ok_or(
+            RendererError::Vulkan(yarvk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+        )?;
+        let target = OffscreenImage {
+            image,
+            format: OFFSCREEN_IMAGE_FORMAT,
+            extent: *resolution,
+        };
+        let rendering_function = T::new(render_device, &target);
+        Ok(Self {
+            target,
+            render_scene: Some(RenderScene::new(render_device)),
+            rendering_function,
+        })
+    }
+    /// Rebuilds the image and rendering function with the device returned by
+    /// `RenderDevice::recover`. The render scene starts empty.
+    pub fn recover(&mut self, render_device: &RenderDevice) -> Result<(), RendererError> {
+        *self = Self::new(render_device, &self.target.extent)?;
+        Ok(())
+    }
+    fn create_color_image(
+        render_device: &RenderDevice,
+        resolution: Extent2D,
+    ) -> Option<Arc<IMemBakImg>> {
+        let device = &render_device.device;
+        let mut image_builder = ContinuousImage::builder(device);
+        image_builder.image_type(ImageType::TYPE_2D);
+        image_builder.format(OFFSCREEN_IMAGE_FORMAT);
+        image_builder.extent(resolution.into());
+        image_builder.mip_levels(1);
+        image_builder.array_layers(1);
+        image_builder.samples(SampleCountFlags::TYPE_1);
+        image_builder.tiling(ImageTiling::OPTIMAL);
+        image_builder.usage(
+            ImageUsageFlags::COLOR_ATTACHMENT
+                | ImageUsageFlags::TRANSFER_SRC
+                | ImageUsageFlags::SAMPLED,
+        );
+        image_builder.sharing_mode(SharingMode::EXCLUSIVE);
+        let image = image_builder.build().ok()?;
+        let memory_requirement = image.get_memory_requirements();
+        let mut images = try_memory_type(
+            memory_requirement,
+            device.physical_device.memory_properties(),
+            None,
+            memory_requirement.size,
+            |memory_type| {
+                ArrayDeviceMemory::new_resources(&device, &image_builder, 1, &memory_type).ok()
+            },
+        )?;
+        images.pop()
+    }
+    pub fn resolution(&self) -> Extent2D {
+        self.target.extent
+    }
+    pub fn format(&self) -> Format {
+        self.target.format
+    }
+    pub fn get_render_scene(&mut self) -> &mut RenderScene {
+        self.render_scene
+            .as_mut()
+            .expect("render scene is lost with the device, `recover` the target first")
+    }
+    /// Copies the color image of the next `render` back to the host. The frame is available
+    /// from the receiver as soon as `render` returned.
+    pub fn capture_frame(
+        &mut self,
+        render_device: &RenderDevice,
+    ) -> Result<FrameCaptureReceiver, RendererError> {
+        let (frame_capture, receiver) =
+            FrameCapture::new(render_device, self.target.extent, self.target.format)?;
+        self.get_render_scene().render_resources.frame_capture = Some(frame_capture);
+        Ok(receiver)
+    }
+    /// Records and submits the scene, then blocks until the GPU finished. The returned image
+    /// is left in `TRANSFER_SRC_OPTIMAL` layout. Returns `RendererError::DeviceLost` once the
+    /// device is lost, until the target is recovered by `recover`. After other errors the
+    /// target keeps rendering with a fresh scene.
+    pub fn render(
+        &mut self,
+        render_device: &RenderDevice,
+    ) -> Result<Arc<IMemBakImg>, RendererError> {
+        let render_scene = self.render_scene.take().ok_or(RendererError::DeviceLost)?;
+        let result = match self.render_frame(render_device, render_scene) {
+            Ok(render_scene) => {
+                self.render_scene = Some(render_scene);
+                Ok(self.target.image.clone())
+            }
+            Err(e) => {
+                if !matches!(e, RendererError::DeviceLost) {
+                    self.render_scene = Some(RenderScene::new(render_device));
+                }
+                Err(e)
+            }
+        };
+        render_device.check_validation_errors();
+        result
+    }
+    fn render_frame(
+        &mut self,
+        render_device: &RenderDevice,
+        render_scene: RenderScene,
+    ) -> Result<RenderScene, RendererError> {
+        let RenderScene {
+            present_resources,
+            record_resources,
+            mut render_resources,
+        } = render_scene;
+        let fence = record_resources.fence;
+        let primary_command_buffer = record_resources.primary_command_buffer;
+        let secondary_command_buffers = record_resources.secondary_command_buffers;
+        let primary_command_buffer_handle = primary_command_buffer.handle();
+        let command_buffer = self.rendering_function.record(
+            &render_device,
+            &self.target.image.handle(),
+            primary_command_buffer,
+            secondary_command_buffers,
+            &mut render_resources,
+            1.0,
+            self.target.extent,
+        );
+        let submit_info = SubmitInfo::builder()
+            .add_one_time_submit_command_buffer(command_buffer)
+            .build();
+        let signaling_fence = Submittable::new()
+            .add_submit_info(submit_info)
+            .submit(&mut render_device.present_queue.lock(), fence)?;
+
+        let (fence, mut submit_result) = signaling_fence.wait()?;
+        let fence = fence.reset()?;
+        let mut primary_command_buffer = submit_result
+            .take_invalid_primary_buffer(&primary_command_buffer_handle)
+            .expect("internal error: no command buffer in result");
+        let mut secondary_command_buffers =
+            Vec::with_capacity(primary_command_buffer.secondary_buffers().len());
+        while let Some(secondary_buffer) = primary_command_buffer.secondary_buffers().pop() {
+            let secondary_buffer = secondary_buffer.reset()?;
+            secondary_command_buffers.push(secondary_buffer);
+        }
+        let primary_command_buffer = primary_command_buffer.reset()?;
+
+        if let Some(frame_capture) = render_resources.frame_capture.take() {
+            frame_capture.finish();
+        }
+        render_resources.clear();
+        Ok(RenderScene {
+            present_resources,
+            record_resources: RecordResources {
+                fence,
+                primary_command_buffer,
+                secondary_command_buffers,
+            },
+            render_resources,
+        })
+    }
+}
@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use tyleri_gpu_utils::memory::array_device_memory::ArrayDeviceMemory;
+use tyleri_gpu_utils::memory::{try_memory_type, IMemBakImg};
+use yarvk::command::command_buffer::CommandBufferInheritanceInfo;
+use yarvk::device_memory::IMemoryRequirements;
+use yarvk::frame_buffer::Framebuffer;
+use yarvk::image_subresource_range::ImageSubresourceRange;
+use yarvk::image_view::{ImageView, ImageViewType};
+use yarvk::physical_device::SharingMode;
+use yarvk::pipeline::pipeline_stage_flags::PipelineStageFlag;
+use yarvk::render_pass::attachment::{AttachmentDescription, AttachmentReference};
+use yarvk::render_pass::render_pass_begin_info::RenderPassBeginInfo;
+use yarvk::render_pass::subpass::{SubpassDependency, SubpassDescription};
+use yarvk::render_pass::RenderPass;
+use yarvk::{
+    AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, ClearDepthStencilValue,
+    ClearValue, ComponentMapping, ComponentSwizzle, ContinuousImage, Extent2D, Format,
+    ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, Rect2D,
+    SampleCountFlags, Viewport, SUBPASS_EXTERNAL,
+};
+
+use crate::render_device::RenderDevice;
+use crate::resource::StaticTexture;
+use crate::RendererError;
+
+pub const RENDER_TEXTURE_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// A color and depth image a `Camera` renders into, see `CameraTarget::Texture`. The color
+/// image is left in `SHADER_READ_ONLY_OPTIMAL` layout and sampled through `texture`.
+pub struct RenderTexture {
+    extent: Extent2D,
+    texture: StaticTexture,
+    pub(crate) render_pass: Arc<RenderPass>,
+    pub(crate) render_pass_begin_info: Arc<RenderPassBeginInfo>,
+    pub(crate) inheritance_info: Arc<CommandBufferInheritanceInfo>,
+}
+
+impl RenderTexture {
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+    /// Usable by mesh renderers and UI elements of the same frame, cameras rendering into
+    /// textures are recorded first. A camera must not sample its own texture.
+    pub fn texture(&self) -> &StaticTexture {
+        &self.texture
+    }
+    /// Covers the whole texture.
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.extent.width as _,
+            height: self.extent.height as _,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+    pub fn scissor(&self) -> Rect2D {
+        Rect2D {
+            offset: Default::default(),
+            extent: self.extent,
+        }
+    }
+    fn create_render_pass(render_device: &RenderDevice) -> Result<Arc<RenderPass>, RendererError> {
+        let depth_attachment_builder = AttachmentDescription::builder()
+            .format(render_device.depth_image_format)
+            .samples(SampleCountFlags::TYPE_1)
+            .load_op(AttachmentLoadOp::CLEAR)
+            .initial_layout(ImageLayout::UNDEFINED)
+            .final_layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let depth_attachment = if render_device.depth_has_stencil() {
+            depth_attachment_builder
+                .stencil_load_op(AttachmentLoadOp::CLEAR)
+                .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+                .build()
+        } else {
+            depth_attachment_builder.build()
+        };
+        let render_pass = RenderPass::builder(&render_device.device)
+            .add_attachment(
+                AttachmentDescription::builder()
+                    .format(RENDER_TEXTURE_FORMAT)
+                    .samples(SampleCountFlags::TYPE_1)
+                    .load_op(AttachmentLoadOp::CLEAR)
+                    .store_op(AttachmentStoreOp::STORE)
+                    .initial_layout(ImageLayout::UNDEFINED)
+                    .final_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .build(),
+            )
+            .add_attachment(depth_attachment)
+            .add_subpass(
+                SubpassDescription::builder()
+                    .add_color_attachment(
+                        AttachmentReference::builder()
+                            .attachment_index(0)
+                            .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                            .build(),
+                    )
+                    .depth_stencil_attachment(
+                        AttachmentReference::builder()
+                            .attachment_index(1)
+                            .layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                            .build(),
+                    )
+                    .build(),
+            )
+            // the previous frame may still sample the texture
+            .add_dependency(
+                SubpassDependency::builder()
+                    .src_subpass(SUBPASS_EXTERNAL)
+                    .dst_subpass(0)
+                    .add_src_stage_mask(PipelineStageFlag::FragmentShader.into())
+                    .add_dst_stage_mask(PipelineStageFlag::ColorAttachmentOutput.into())
+                    .src_access_mask(AccessFlags::SHADER_READ)
+                    .dst_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .build(),
+            )
+            // later passes of the frame sample it
+            .add_dependency(
+                SubpassDependency::builder()
+                    .src_subpass(0)
+                    .dst_subpass(SUBPASS_EXTERNAL)
+                    .add_src_stage_mask(PipelineStageFlag::ColorAttachmentOutput.into())
+                    .add_dst_stage_mask(PipelineStageFlag::FragmentShader.into())
+                    .src_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(AccessFlags::SHADER_READ)
+                    .build(),
+            )
+            .build()?;
+        Ok(render_pass)
+    }
+    fn create_depth_image(
+        render_device: &RenderDevice,
+        extent: Extent2D,
+    ) -> Option<Arc<IMemBakImg>> {
+        let device = &render_device.device;
+        let mut image_builder = ContinuousImage::builder(device);
+        image_builder.image_type(ImageType::TYPE_2D);
+        image_builder.format(render_device.depth_image_format);
+        image_builder.extent(extent.into());
+        image_builder.mip_levels(1);
+        image_builder.array_layers(1);
+        image_builder.samples(SampleCountFlags::TYPE_1);
+        image_builder.tiling(ImageTiling::OPTIMAL);
+        image_builder.usage(ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
+        image_builder.sharing_mode(SharingMode::EXCLUSIVE);
+        let image = image_builder.build().ok()?;
+        let memory_requirement = image.get_memory_requirements();
+        let mut images = try_memory_type(
+            memory_requirement,
+            device.physical_device.memory_properties(),
+            None,
+            memory_requirement.size,
+            |memory_type| {
+                ArrayDeviceMemory::new_resources(&device, &image_builder, 1, &memory_type).ok()
+            },
+        )?;
+        images.pop()
+    }
+}
+
+impl RenderDevice {
+    /// Creates a texture for `CameraTarget::Texture`, cleared to transparent black until a
+    /// camera renders into it.
+    pub fn create_render_texture(
+        &self,
+        extent: Extent2D,
+    ) -> Result<Arc<RenderTexture>, RendererError> {
+        let mut builder = ContinuousImage::builder(&self.device);
+        builder.image_type(ImageType::TYPE_2D);
+        builder.format(RENDER_TEXTURE_FORMAT);
+        builder.mip_levels(1);
+        builder.array_layers(1);
+        builder.samples(SampleCountFlags::TYPE_1);
+        builder.tiling(ImageTiling::OPTIMAL);
+        builder.usage(
+            ImageUsageFlags::COLOR_ATTACHMENT
+                | ImageUsageFlags::SAMPLED
+                | ImageUsageFlags::TRANSFER_DST,
+        );
+        builder.sharing_mode(SharingMode::EXCLUSIVE);
+        let memory_type = &self
+            .memory_allocator
+            .resource_infos
+            .texture_info
+            .memory_type;
+        // uploading zeros moves the image into the sampled layout before its first render
+        let color_image = self
+            .create_image(
+                builder,
+                memory_type,
+                vec![(
+                    extent,
+                    Box::new(|pixels: &mut [u8]| pixels.fill(0))
+                        as Box<dyn FnOnce(&mut [u8]) + Send + Sync>,
+                )],
+            )?
+            .pop()
+            .expect("internal error: no render texture image created");
+        let color_image_view = ImageView::builder(color_image)
+            .view_type(ImageViewType::Type2d)
+            .format(RENDER_TEXTURE_FORMAT)
+            .components(ComponentMapping {
+                r: ComponentSwizzle::R,
+                g: ComponentSwizzle::G,
+                b: ComponentSwizzle::B,
+                a: ComponentSwizzle::A,
+            })
+            .subresource_range(
+                ImageSubresourceRange::builder()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(1)
+                    .build(),
+            )
+            .build()?;
+        let depth_image = RenderTexture::create_depth_image(self, extent).ok_or(
+            RendererError::Vulkan(yarvk::Result::ERROR_OUT_OF_DEVICE_MEMORY),
+        )?;
+        let depth_image_view = ImageView::builder(depth_image)
+            .subresource_range(
+                ImageSubresourceRange::builder()
+                    .aspect_mask(self.depth_aspect_mask())
+                    .level_count(1)
+                    .layer_count(1)
+                    .build(),
+            )
+            .format(self.depth_image_format)
+            .view_type(ImageViewType::Type2d)
+            .build()?;
+        let render_pass = RenderTexture::create_render_pass(self)?;
+        let framebuffer = Framebuffer::builder(render_pass.clone())
+            .add_attachment(0, color_image_view.clone())
+            .add_attachment(1, depth_image_view)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1)
+            .build(&self.device)?;
+        let render_pass_begin_info = Arc::new(
+            RenderPassBeginInfo::builder(render_pass.clone(), framebuffer)
+                .render_area(extent.into())
+                .add_clear_value(ClearValue {
+                    color: ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 0.0],
+                    },
+                })
+                .add_clear_value(ClearValue {
+                    depth_stencil: ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: self.stencil_clear_value,
+                    },
+                })
+                .build(),
+        );
+        let inheritance_info = CommandBufferInheritanceInfo::builder()
+            .render_pass(render_pass.clone())
+            .subpass(0)
+            .build();
+        let texture = self
+            .create_image_descriptor_sets(&[color_image_view])
+            .pop()
+            .expect("internal error: no descriptor set created");
+        Ok(Arc::new(RenderTexture {
+            extent,
+            texture,
+            render_pass,
+            render_pass_begin_info,
+            inheritance_info,
+        }))
+    }
+}
@@ -1,35 +1,40 @@
-use std::mem::MaybeUninit;
 use std::sync::Arc;
 
-use rayon::iter::IntoParallelIterator;
-use rayon::iter::ParallelIterator;
-use rustc_hash::FxHashMap;
-use yarvk::command::command_buffer::Level::{PRIMARY, SECONDARY};
-use yarvk::command::command_buffer::TransientCommandBuffer;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use tyleri_gpu_utils::queue::parallel_recording_queue::ParallelRecordingQueue;
+use yarvk::command::command_buffer::CommandBuffer;
+use yarvk::command::command_buffer::Level::PRIMARY;
+use yarvk::command::command_buffer::RenderPassScope::OUTSIDE;
+use yarvk::command::command_buffer::State::EXECUTABLE;
 use yarvk::extensions::PhysicalInstanceExtensionType;
-use yarvk::fence::{Fence, SignalingFence};
+use yarvk::fence::UnsignaledFence;
 use yarvk::pipeline::pipeline_stage_flags::PipelineStageFlag;
-use yarvk::queue::submit_info::{SubmitInfo, SubmitResult, Submittable};
+use yarvk::queue::submit_info::{SubmitInfo, Submittable};
 use yarvk::surface::Surface;
 use yarvk::swapchain::PresentInfo;
 use yarvk::{BoundContinuousImage, Extent2D, Handle};
 
 use crate::render_device::RenderDevice;
-use crate::render_scene::{PresentResources, RecordResources};
-use crate::render_scene::{RenderResources, RenderScene};
-use crate::render_window::swapchain::ImageViewSwapchain;
+use crate::render_scene::{PresentResources, RenderResources, RenderScene};
+use crate::render_target::frame_capture::{FrameCapture, FrameCaptureReceiver};
+use crate::render_window::frame_ring::{FrameRing, InFlightFrame};
+use crate::render_window::swapchain::{ImageViewSwapchain, PresentModePreference, SwapchainConfig};
 use crate::rendering_function::RenderingFunction;
-use crate::WindowHandle;
+use crate::{RendererError, WindowHandle};
 
+mod frame_ring;
 pub mod present_image_view;
 pub mod swapchain;
 
 pub type ImageHandle = u64;
 
-struct UsingResources {
-    present_resources: PresentResources,
+/// A frame recorded by `RenderWindow::render`, waiting to be submitted.
+struct RecordedFrame {
+    image: Arc<BoundContinuousImage>,
+    command_buffer: CommandBuffer<{ PRIMARY }, { EXECUTABLE }, { OUTSIDE }>,
+    fence: UnsignaledFence,
     primary_command_buffer_handle: u64,
-    record_resources: SignalingFence<SubmitResult>,
+    present_resources: PresentResources,
     render_resources: RenderResources,
 }
 
@@ -37,8 +42,11 @@ pub struct RenderWindow<T: RenderingFunction> {
     window_handle: WindowHandle,
     scale_factor: f64,
     swapchain: ImageViewSwapchain,
-    available_render_scene: RenderScene,
-    using_resources: FxHashMap<ImageHandle /*image handle*/, UsingResources>,
+    // asked by the user, the surface may override it
+    resolution: Extent2D,
+    swapchain_outdated: bool,
+    frame_ring: FrameRing,
+    device_lost: bool,
     rendering_function: T,
 }
 
@@ -57,103 +65,212 @@ impl<T: RenderingFunction> RenderWindow<T> {
         scale_factor: f64,
         render_device: &RenderDevice,
         resolution: &Extent2D,
-    ) -> Self {
-        let device = &render_device.device;
+    ) -> Result<Self, RendererError> {
+        Self::new_with_config(
+            window_handle,
+            scale_factor,
+            render_device,
+            resolution,
+            SwapchainConfig::default(),
+        )
+    }
+    pub fn new_with_config(
+        window_handle: WindowHandle,
+        scale_factor: f64,
+        render_device: &RenderDevice,
+        resolution: &Extent2D,
+        swapchain_config: SwapchainConfig,
+    ) -> Result<Self, RendererError> {
         let khr_surface_ext = render_device
             .device
             .physical_device
             .instance
             .get_extension::<{ PhysicalInstanceExtensionType::KhrSurface }>()
-            .unwrap();
+            .ok_or(RendererError::UnsupportedSurface)?;
         let surface = Surface::get_physical_device_surface_support(
             khr_surface_ext.clone(),
             window_handle.display_handle,
             window_handle.window_handle,
             &render_device.present_queue_family,
-        )
-        .unwrap()
-        .expect("cannot find surface for a give device");
-        let swapchain = ImageViewSwapchain::new(render_device, &surface, resolution);
+        )?
+        .ok_or(RendererError::UnsupportedSurface)?;
+        let swapchain =
+            ImageViewSwapchain::new(render_device, &surface, resolution, swapchain_config)?;
         let rendering_function = T::new(render_device, &swapchain);
-        let available_render_scene = RenderScene::new(render_device);
-        let using_resources = swapchain
-            .swapchain
-            .get_swapchain_images()
-            .iter()
-            .map(|image| {
-                let mut submit_result = SubmitResult::default();
-                let present_queue_family = &render_device.present_queue_family;
-                let primary_command_buffer = TransientCommandBuffer::<{ PRIMARY }>::new(
-                    &device,
-                    present_queue_family.clone().clone(),
-                )
-                .unwrap();
-                // TODO configurable command buffer counts
-                let secondary_command_buffers = (0..rayon::current_num_threads())
-                    .into_par_iter()
-                    .map(|_| {
-                        TransientCommandBuffer::<{ SECONDARY }>::new(
-                            &device,
-                            present_queue_family.clone().clone(),
-                        )
-                        .unwrap()
-                    })
-                    .collect();
-                let primary_command_buffer_handle = primary_command_buffer.handle();
-                submit_result.add_primary_buffer(primary_command_buffer, secondary_command_buffers);
-                let fence = Fence::new_signaling(device, submit_result).unwrap();
-                (
-                    image.handle(),
-                    UsingResources {
-                        present_resources: PresentResources::new(device),
-                        primary_command_buffer_handle,
-                        record_resources: fence,
-                        render_resources: RenderResources::new(render_device),
-                    },
-                )
-            })
-            .collect();
+        let frame_ring = FrameRing::new(render_device, swapchain.config.frames_in_flight);
 
-        Self {
+        Ok(Self {
             window_handle,
             scale_factor,
             swapchain,
-            available_render_scene,
-            using_resources,
+            resolution: *resolution,
+            swapchain_outdated: false,
+            frame_ring,
+            device_lost: false,
             rendering_function,
+        })
+    }
+    /// Rebuilds the swapchain and everything depending on its images. Returns false if the
+    /// surface has no area, e.g. the window is minimized; the swapchain stays outdated then.
+    fn recreate_swapchain(&mut self, render_device: &RenderDevice) -> Result<bool, RendererError> {
+        let extent =
+            ImageViewSwapchain::surface_extent(&self.swapchain.swapchain.surface, &self.resolution);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(false);
         }
+        self.frame_ring.wait_all()?;
+        self.swapchain = self.swapchain.recreate(render_device, &self.resolution)?;
+        self.rendering_function
+            .on_swapchain_recreated(render_device, &self.swapchain);
+        self.swapchain_outdated = false;
+        Ok(true)
+    }
+    /// Changes the present mode preference, the swapchain is rebuilt by the next `render`.
+    pub fn set_present_modes(&mut self, present_modes: Vec<PresentModePreference>) {
+        self.swapchain.config = self.swapchain.config.clone().present_modes(present_modes);
+        self.swapchain_outdated = true;
     }
-    pub fn render(&mut self, render_device: &RenderDevice) {
-        let mut tmp: MaybeUninit<RenderScene> = MaybeUninit::uninit();
-        std::mem::swap(&mut self.available_render_scene, unsafe {
-            &mut *tmp.as_mut_ptr()
+    /// Resizes the swapchain to `new_extent`, it is rebuilt lazily by the next `render`.
+    pub fn resize(&mut self, new_extent: Extent2D) {
+        self.resolution = new_extent;
+        self.swapchain_outdated = true;
+    }
+    /// Records, submits and presents the render scene. Returns `RendererError::DeviceLost`
+    /// once the device is lost, until the window is recovered by `recover`. After other errors
+    /// the window keeps rendering with a fresh scene.
+    pub fn render(&mut self, render_device: &RenderDevice) -> Result<(), RendererError> {
+        let result = self.record_frame(render_device).and_then(|recorded_frame| {
+            recorded_frame.map_or(Ok(()), |recorded_frame| {
+                self.submit_frame(recorded_frame, &mut render_device.present_queue.lock())
+            })
         });
+        let result = self.end_frame(render_device, result);
+        render_device.check_validation_errors();
+        result
+    }
+    /// Renders several windows of the device like `render`, recording them in parallel and
+    /// submitting every frame in one lock of the shared queue. Returns the result of each
+    /// window in order.
+    pub fn render_all(
+        windows: &mut [&mut Self],
+        render_device: &RenderDevice,
+    ) -> Vec<Result<(), RendererError>>
+    where
+        T: Send,
+    {
+        let recorded_frames: Vec<_> = windows
+            .par_iter_mut()
+            .map(|window| window.record_frame(render_device))
+            .collect();
+        let mut present_queue = render_device.present_queue.lock();
+        let results: Vec<_> = windows
+            .iter_mut()
+            .zip(recorded_frames)
+            .map(|(window, recorded_frame)| {
+                recorded_frame.and_then(|recorded_frame| {
+                    recorded_frame.map_or(Ok(()), |recorded_frame| {
+                        window.submit_frame(recorded_frame, &mut present_queue)
+                    })
+                })
+            })
+            .collect();
+        drop(present_queue);
+        let results = windows
+            .par_iter_mut()
+            .zip(results)
+            .map(|(window, result)| window.end_frame(render_device, result))
+            .collect();
+        render_device.check_validation_errors();
+        results
+    }
+    /// Acquires an image and records the current scene, none if there is nothing to present to.
+    fn record_frame(
+        &mut self,
+        render_device: &RenderDevice,
+    ) -> Result<Option<RecordedFrame>, RendererError> {
+        if self.device_lost {
+            return Err(RendererError::DeviceLost);
+        }
+        if self.frame_ring.current_scene().is_none() {
+            // dropped by a frame that panicked after taking the scene
+            self.frame_ring.rebuild_lost(render_device);
+        }
+        if self.swapchain_outdated && !self.recreate_swapchain(render_device)? {
+            // nothing to present to while minimized
+            self.skip_frame();
+            return Ok(None);
+        }
+        if let Some(max_frame_latency) = self.swapchain.config.max_frame_latency {
+            self.frame_ring.limit_latency(max_frame_latency)?;
+        }
+        let image = match self.swapchain.swapchain.acquire_next_image_semaphore_only(
+            u64::MAX,
+            &self
+                .frame_ring
+                .current_scene()
+                .expect("internal error: current frame is not recording")
+                .present_resources
+                .present_complete_semaphore,
+        ) {
+            Ok(image) => image,
+            Err(yarvk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_outdated = true;
+                self.skip_frame();
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
         let RenderScene {
-            mut present_resources,
+            present_resources,
             record_resources,
-            render_resources,
-        } = unsafe { tmp.assume_init() };
-        let image = self
-            .swapchain
-            .swapchain
-            .acquire_next_image_semaphore_only(
-                u64::MAX,
-                &present_resources.present_complete_semaphore,
-            )
-            .unwrap();
-        let fence = record_resources.fence;
+            mut render_resources,
+        } = self
+            .frame_ring
+            .take_current_scene()
+            .expect("internal error: current frame is not recording");
         let primary_command_buffer = record_resources.primary_command_buffer;
-        let secondary_command_buffers = record_resources.secondary_command_buffers;
         let primary_command_buffer_handle = primary_command_buffer.handle();
         let command_buffer = self.rendering_function.record(
             &render_device,
             &image.handle(),
             primary_command_buffer,
-            secondary_command_buffers,
-            &render_resources,
+            record_resources.secondary_command_buffers,
+            &mut render_resources,
             self.scale_factor,
             self.swapchain.swapchain.image_extent.clone(),
         );
+        Ok(Some(RecordedFrame {
+            image,
+            command_buffer,
+            fence: record_resources.fence,
+            primary_command_buffer_handle,
+            present_resources,
+            render_resources,
+        }))
+    }
+    /// Empties the current scene without rendering it, it is filled again for the next frame.
+    /// A requested frame capture waits for the next rendered frame.
+    fn skip_frame(&mut self) {
+        if let Some(render_scene) = self.frame_ring.current_scene() {
+            let render_resources = &mut render_scene.render_resources;
+            let frame_capture = render_resources.frame_capture.take();
+            render_resources.clear();
+            render_resources.frame_capture = frame_capture;
+        }
+    }
+    fn submit_frame(
+        &mut self,
+        recorded_frame: RecordedFrame,
+        present_queue: &mut ParallelRecordingQueue,
+    ) -> Result<(), RendererError> {
+        let RecordedFrame {
+            image,
+            command_buffer,
+            fence,
+            primary_command_buffer_handle,
+            present_resources,
+            render_resources,
+        } = recorded_frame;
         let submit_info = SubmitInfo::builder()
             .add_wait_semaphore(
                 &present_resources.present_complete_semaphore,
@@ -162,72 +279,89 @@ impl<T: RenderingFunction> RenderWindow<T> {
             .add_one_time_submit_command_buffer(command_buffer)
             .add_signal_semaphore(&present_resources.rendering_complete_semaphore)
             .build();
-        let mut present_queue = render_device
-            .present_queues
-            .pop()
-            .expect("internal error: no queue is available");
         let signaling_fence = Submittable::new()
             .add_submit_info(submit_info)
-            .submit(&mut present_queue, fence)
-            .unwrap();
+            .submit(present_queue, fence)?;
+        let frame = self.frame_ring.submitted(InFlightFrame {
+            present_resources,
+            primary_command_buffer_handle,
+            record_resources: signaling_fence,
+            render_resources,
+        });
         let mut present_info = PresentInfo::builder()
             .add_swapchain_and_image(&mut self.swapchain.swapchain, &image)
-            .add_wait_semaphore(&mut present_resources.rendering_complete_semaphore)
+            .add_wait_semaphore(&mut frame.present_resources.rendering_complete_semaphore)
             .build();
-        present_queue.queue_present(&mut present_info).unwrap();
-        render_device.present_queues.push(present_queue);
-
-        // wait previous frame finished
-        let mut old_resources = self
-            .using_resources
-            .insert(
-                image.handle(),
-                UsingResources {
-                    present_resources,
-                    primary_command_buffer_handle,
-                    record_resources: signaling_fence,
-                    render_resources,
-                },
-            )
-            .expect("internal error: not pending resources in last frame");
-        let (fence, mut submit_result) = old_resources.record_resources.wait().unwrap();
-        let fence = fence.reset().unwrap();
-        let mut primary_command_buffer = submit_result
-            .take_invalid_primary_buffer(&old_resources.primary_command_buffer_handle)
-            .expect("internal error: no command buffer in result");
-        let mut secondary_command_buffers =
-            Vec::with_capacity(primary_command_buffer.secondary_buffers().len());
-        while let Some(secondary_buffer) = primary_command_buffer.secondary_buffers().pop() {
-            let secondary_buffer = secondary_buffer.reset().unwrap();
-            secondary_command_buffers.push(secondary_buffer);
+        match present_queue.queue_present(&mut present_info) {
+            Ok(suboptimal) => self.swapchain_outdated |= suboptimal,
+            Err(yarvk::Result::ERROR_OUT_OF_DATE_KHR) => self.swapchain_outdated = true,
+            Err(e) => return Err(e.into()),
         }
-        let primary_command_buffer = primary_command_buffer.reset().unwrap();
-
-        old_resources.render_resources.clear();
-        let mut new_presenting_scene = RenderScene {
-            present_resources: old_resources.present_resources,
-            record_resources: RecordResources {
-                fence,
-                primary_command_buffer,
-                secondary_command_buffers,
-            },
-            render_resources: old_resources.render_resources,
-        };
-        std::mem::swap(&mut self.available_render_scene, &mut new_presenting_scene);
-        std::mem::forget(new_presenting_scene);
+        self.frame_ring.presented();
+        Ok(())
+    }
+    fn end_frame(
+        &mut self,
+        render_device: &RenderDevice,
+        result: Result<(), RendererError>,
+    ) -> Result<(), RendererError> {
+        // the frame is in flight even if presenting failed, the next slot is waited anyway
+        let result = result.and(self.frame_ring.advance());
+        match &result {
+            Err(RendererError::DeviceLost) => self.device_lost = true,
+            Err(_) => self.frame_ring.rebuild_lost(render_device),
+            Ok(_) => {}
+        }
+        result
+    }
+    /// Rebuilds the surface, swapchain and rendering function with the device returned by
+    /// `RenderDevice::recover`. The render scene starts empty. Takes the window by value, a
+    /// native window accepts one surface at a time, so the old one is destroyed first.
+    pub fn recover(self, render_device: &RenderDevice) -> Result<Self, RendererError> {
+        let window_handle = self.window_handle.clone();
+        let scale_factor = self.scale_factor;
+        let resolution = self.resolution;
+        let swapchain_config = self.swapchain.config.clone();
+        drop(self);
+        Self::new_with_config(
+            window_handle,
+            scale_factor,
+            render_device,
+            &resolution,
+            swapchain_config,
+        )
     }
     pub fn scale_factor(&self) -> f64 {
         self.scale_factor
     }
     pub fn get_render_scene(&mut self) -> &mut RenderScene {
-        &mut self.available_render_scene
+        self.frame_ring
+            .current_scene()
+            .expect("render scene is lost with the device, `recover` the window first")
+    }
+    /// Copies the next rendered frame back to the host. The frame arrives in the receiver once
+    /// its fence signaled, which is observed by a later `render` call. Fails with
+    /// `RendererError::UnsupportedFeature` if the swapchain images cannot be copied.
+    pub fn capture_frame(
+        &mut self,
+        render_device: &RenderDevice,
+    ) -> Result<FrameCaptureReceiver, RendererError> {
+        if !self.swapchain.capturable {
+            return Err(RendererError::UnsupportedFeature(
+                "capturing swapchain images without transfer source usage".to_string(),
+            ));
+        }
+        let (frame_capture, receiver) = FrameCapture::new(
+            render_device,
+            self.swapchain.swapchain.image_extent,
+            self.swapchain.format,
+        )?;
+        self.get_render_scene().render_resources.frame_capture = Some(frame_capture);
+        Ok(receiver)
     }
 }
 impl<T: RenderingFunction> Drop for RenderWindow<T> {
     fn drop(&mut self) {
-        let resources = std::mem::take(&mut self.using_resources);
-        resources.into_iter().for_each(|(_, resources)| {
-            resources.record_resources.wait().unwrap();
-        })
+        let _ = self.frame_ring.wait_all();
     }
 }
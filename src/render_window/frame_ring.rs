@@ -0,0 +1,166 @@
+use yarvk::fence::SignalingFence;
+use yarvk::queue::submit_info::SubmitResult;
+
+use crate::render_device::RenderDevice;
+use crate::render_scene::{PresentResources, RecordResources, RenderResources, RenderScene};
+use crate::RendererError;
+
+/// Resources of a submitted frame, reusable once its fence signaled.
+pub(crate) struct InFlightFrame {
+    pub(crate) present_resources: PresentResources,
+    pub(crate) primary_command_buffer_handle: u64,
+    pub(crate) record_resources: SignalingFence<SubmitResult>,
+    pub(crate) render_resources: RenderResources,
+}
+
+impl InFlightFrame {
+    /// Waits for the frame and resets its resources to be filled again.
+    fn recycle(self) -> Result<RenderScene, RendererError> {
+        let InFlightFrame {
+            present_resources,
+            primary_command_buffer_handle,
+            record_resources,
+            mut render_resources,
+        } = self;
+        let (fence, mut submit_result) = record_resources.wait()?;
+        if let Some(frame_capture) = render_resources.frame_capture.take() {
+            frame_capture.finish();
+        }
+        let fence = fence.reset()?;
+        let mut primary_command_buffer = submit_result
+            .take_invalid_primary_buffer(&primary_command_buffer_handle)
+            .expect("internal error: no command buffer in result");
+        let mut secondary_command_buffers =
+            Vec::with_capacity(primary_command_buffer.secondary_buffers().len());
+        while let Some(secondary_buffer) = primary_command_buffer.secondary_buffers().pop() {
+            let secondary_buffer = secondary_buffer.reset()?;
+            secondary_command_buffers.push(secondary_buffer);
+        }
+        let primary_command_buffer = primary_command_buffer.reset()?;
+
+        render_resources.clear();
+        Ok(RenderScene {
+            present_resources,
+            record_resources: RecordResources {
+                fence,
+                primary_command_buffer,
+                secondary_command_buffers,
+            },
+            render_resources,
+        })
+    }
+}
+
+enum FrameSlot {
+    /// The scene is filled until the next `RenderWindow::render`.
+    Recording(RenderScene),
+    /// Submitted but not presented, e.g. presenting failed.
+    Submitted(InFlightFrame),
+    /// Submitted and queued for presentation.
+    Presented(InFlightFrame),
+    /// The resources were dropped by a failed or unwound frame.
+    Lost,
+}
+
+/// One slot per frame in flight, the current slot is recording and the following ones are
+/// the oldest frames.
+pub(crate) struct FrameRing {
+    slots: Vec<FrameSlot>,
+    current: usize,
+}
+
+impl FrameRing {
+    pub(crate) fn new(render_device: &RenderDevice, frames_in_flight: usize) -> Self {
+        Self {
+            slots: (0..frames_in_flight)
+                .map(|_| FrameSlot::Recording(RenderScene::new(render_device)))
+                .collect(),
+            current: 0,
+        }
+    }
+    pub(crate) fn current_scene(&mut self) -> Option<&mut RenderScene> {
+        match &mut self.slots[self.current] {
+            FrameSlot::Recording(render_scene) => Some(render_scene),
+            _ => None,
+        }
+    }
+    /// Takes the scene to record it, the slot is lost until `submitted` is called.
+    pub(crate) fn take_current_scene(&mut self) -> Option<RenderScene> {
+        match std::mem::replace(&mut self.slots[self.current], FrameSlot::Lost) {
+            FrameSlot::Recording(render_scene) => Some(render_scene),
+            slot => {
+                self.slots[self.current] = slot;
+                None
+            }
+        }
+    }
+    pub(crate) fn submitted(&mut self, frame: InFlightFrame) -> &mut InFlightFrame {
+        self.slots[self.current] = FrameSlot::Submitted(frame);
+        match &mut self.slots[self.current] {
+            FrameSlot::Submitted(frame) => frame,
+            _ => unreachable!(),
+        }
+    }
+    pub(crate) fn presented(&mut self) {
+        if let FrameSlot::Submitted(frame) =
+            std::mem::replace(&mut self.slots[self.current], FrameSlot::Lost)
+        {
+            self.slots[self.current] = FrameSlot::Presented(frame);
+        }
+    }
+    /// Moves to the next slot if the current frame was submitted, waits for the next frame if
+    /// it is still in flight.
+    pub(crate) fn advance(&mut self) -> Result<(), RendererError> {
+        if !matches!(
+            self.slots[self.current],
+            FrameSlot::Submitted(_) | FrameSlot::Presented(_)
+        ) {
+            return Ok(());
+        }
+        self.current = (self.current + 1) % self.slots.len();
+        self.recycle(self.current)
+    }
+    /// Waits for the oldest frames until less than `max_frame_latency` are in flight.
+    pub(crate) fn limit_latency(&mut self, max_frame_latency: usize) -> Result<(), RendererError> {
+        for offset in 1..self.slots.len() {
+            let in_flight = self
+                .slots
+                .iter()
+                .filter(|slot| matches!(slot, FrameSlot::Submitted(_) | FrameSlot::Presented(_)))
+                .count();
+            if in_flight < max_frame_latency {
+                break;
+            }
+            self.recycle((self.current + offset) % self.slots.len())?;
+        }
+        Ok(())
+    }
+    /// Waits for every frame in flight, returns the first error after trying all of them.
+    pub(crate) fn wait_all(&mut self) -> Result<(), RendererError> {
+        let mut result = Ok(());
+        for index in 0..self.slots.len() {
+            // the other frames are still waited, nothing executes anymore if the device was lost
+            result = result.and(self.recycle(index));
+        }
+        result
+    }
+    /// Rebuilds the scenes dropped by a failed frame, the device must still be valid.
+    pub(crate) fn rebuild_lost(&mut self, render_device: &RenderDevice) {
+        for slot in &mut self.slots {
+            if let FrameSlot::Lost = slot {
+                *slot = FrameSlot::Recording(RenderScene::new(render_device));
+            }
+        }
+    }
+    fn recycle(&mut self, index: usize) -> Result<(), RendererError> {
+        let frame = match std::mem::replace(&mut self.slots[index], FrameSlot::Lost) {
+            FrameSlot::Submitted(frame) | FrameSlot::Presented(frame) => frame,
+            slot => {
+                self.slots[index] = slot;
+                return Ok(());
+            }
+        };
+        self.slots[index] = FrameSlot::Recording(frame.recycle()?);
+        Ok(())
+    }
+}
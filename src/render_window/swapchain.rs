@@ -1,15 +1,153 @@
 use std::sync::Arc;
 
 use yarvk::extensions::PhysicalDeviceExtensionType;
+use yarvk::image_subresource_range::ImageSubresourceRange;
+use yarvk::image_view::{ImageView, ImageViewType};
 use yarvk::physical_device::SharingMode;
 use yarvk::surface::Surface;
 use yarvk::swapchain::Swapchain;
-use yarvk::{CompositeAlphaFlagsKHR, Extent2D, PresentModeKHR, SurfaceTransformFlagsKHR};
+use yarvk::{
+    ColorSpaceKHR, ComponentMapping, ComponentSwizzle, CompositeAlphaFlagsKHR, Extent2D, Format,
+    Handle, ImageAspectFlags, ImageLayout, ImageUsageFlags, PresentModeKHR, SurfaceFormatKHR,
+    SurfaceTransformFlagsKHR,
+};
 
 use crate::render_device::RenderDevice;
+use crate::render_target::hdr::HdrConfig;
+use crate::render_target::RenderTarget;
+use crate::render_window::ImageHandle;
+use crate::RendererError;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PresentModePreference {
+    /// Waits for vertical blank, always supported.
+    Vsync,
+    /// Waits for vertical blank, but presents late frames immediately.
+    RelaxedVsync,
+    /// Waits for vertical blank, newer frames replace the queued one. Low latency without tearing.
+    Mailbox,
+    /// Presents immediately, frame rates are uncapped and may tear.
+    Immediate,
+}
+
+impl PresentModePreference {
+    fn present_mode(&self) -> PresentModeKHR {
+        match self {
+            PresentModePreference::Vsync => PresentModeKHR::FIFO,
+            PresentModePreference::RelaxedVsync => PresentModeKHR::FIFO_RELAXED,
+            PresentModePreference::Mailbox => PresentModeKHR::MAILBOX,
+            PresentModePreference::Immediate => PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+const PREFERRED_SURFACE_FORMATS: [Format; 2] = [Format::B8G8R8A8_SRGB, Format::R8G8B8A8_SRGB];
+
+#[derive(Clone)]
+pub struct SwapchainConfig {
+    present_modes: Vec<PresentModePreference>,
+    surface_format: Option<SurfaceFormatKHR>,
+    hdr: Option<HdrConfig>,
+    pub(crate) frames_in_flight: usize,
+    pub(crate) max_frame_latency: Option<usize>,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            present_modes: vec![PresentModePreference::Vsync],
+            surface_format: None,
+            hdr: None,
+            frames_in_flight: 2,
+            max_frame_latency: None,
+        }
+    }
+}
+
+impl SwapchainConfig {
+    /// Present modes in order of preference, the first one supported by the surface is used.
+    /// Falls back to `Vsync` if none is supported.
+    pub fn present_modes(mut self, present_modes: Vec<PresentModePreference>) -> Self {
+        self.present_modes = present_modes;
+        self
+    }
+    /// Overrides the sRGB preference, used if the surface supports it.
+    pub fn surface_format(mut self, surface_format: SurfaceFormatKHR) -> Self {
+        self.surface_format = Some(surface_format);
+        self
+    }
+    /// Opts in HDR output, needs `RenderDeviceBuilder::hdr_output` for the surface to offer
+    /// HDR color spaces.
+    pub fn hdr(mut self, hdr: HdrConfig) -> Self {
+        self.hdr = Some(hdr);
+        self
+    }
+    /// Render scenes recycled by the window, independent of the swapchain image count. One is
+    /// filled while the others are in flight, 2 by default, at least 1.
+    pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+        self.frames_in_flight = frames_in_flight.max(1);
+        self
+    }
+    /// Waits before acquiring until less than `max_frame_latency` frames are in flight, e.g. 1
+    /// waits for the previous frame to reduce input lag. Bounded by `frames_in_flight`.
+    pub fn max_frame_latency(mut self, max_frame_latency: usize) -> Self {
+        self.max_frame_latency = Some(max_frame_latency.max(1));
+        self
+    }
+    fn choose_surface_format(&self, surface: &Arc<Surface>) -> SurfaceFormatKHR {
+        let supported_formats = surface.get_physical_device_surface_formats();
+        // the surface has no preferred format
+        if supported_formats.len() == 1 && supported_formats[0].format == Format::UNDEFINED {
+            return self.surface_format.unwrap_or(SurfaceFormatKHR {
+                format: PREFERRED_SURFACE_FORMATS[0],
+                color_space: ColorSpaceKHR::SRGB_NONLINEAR,
+            });
+        }
+        if let Some(surface_format) = self.surface_format {
+            if supported_formats.contains(&surface_format) {
+                return surface_format;
+            }
+        }
+        if let Some(hdr) = &self.hdr {
+            let hdr_format = hdr.outputs.iter().find_map(|output| {
+                supported_formats.iter().cloned().find(|surface_format| {
+                    surface_format.color_space == output.color_space()
+                        && output.formats().contains(&surface_format.format)
+                })
+            });
+            if let Some(hdr_format) = hdr_format {
+                return hdr_format;
+            }
+        }
+        PREFERRED_SURFACE_FORMATS
+            .iter()
+            .find_map(|format| {
+                supported_formats.iter().cloned().find(|surface_format| {
+                    surface_format.format == *format
+                        && surface_format.color_space == ColorSpaceKHR::SRGB_NONLINEAR
+                })
+            })
+            .unwrap_or(supported_formats[0])
+    }
+    fn choose_present_mode(&self, surface: &Arc<Surface>) -> PresentModeKHR {
+        let supported_present_modes = surface.get_physical_device_surface_present_modes();
+        self.present_modes
+            .iter()
+            .map(PresentModePreference::present_mode)
+            .find(|present_mode| supported_present_modes.contains(present_mode))
+            // FIFO is required to be supported
+            .unwrap_or(PresentModeKHR::FIFO)
+    }
+}
 
 pub struct ImageViewSwapchain {
     pub swapchain: Swapchain,
+    pub format: Format,
+    pub color_space: ColorSpaceKHR,
+    pub present_mode: PresentModeKHR,
+    /// Whether swapchain images can be copied back to the host.
+    pub capturable: bool,
+    pub(crate) config: SwapchainConfig,
 }
 
 impl ImageViewSwapchain {
@@ -17,13 +155,45 @@ impl ImageViewSwapchain {
         render_device: &RenderDevice,
         surface: &Arc<Surface>,
         resolution: &Extent2D,
-    ) -> Self {
+        config: SwapchainConfig,
+    ) -> Result<Self, RendererError> {
+        Self::create(render_device, surface, resolution, config, None)
+    }
+    /// Builds a swapchain for the same surface with the current config, this one is retired
+    /// and must not be presented anymore.
+    pub fn recreate(
+        &self,
+        render_device: &RenderDevice,
+        resolution: &Extent2D,
+    ) -> Result<Self, RendererError> {
+        Self::create(
+            render_device,
+            &self.swapchain.surface,
+            resolution,
+            self.config.clone(),
+            Some(&self.swapchain),
+        )
+    }
+    /// The extent a swapchain for `surface` would get, zero sized while the window is minimized.
+    pub fn surface_extent(surface: &Arc<Surface>, resolution: &Extent2D) -> Extent2D {
+        let surface_capabilities = surface.get_physical_device_surface_capabilities();
+        match surface_capabilities.current_extent.width {
+            u32::MAX => *resolution,
+            _ => surface_capabilities.current_extent,
+        }
+    }
+    fn create(
+        render_device: &RenderDevice,
+        surface: &Arc<Surface>,
+        resolution: &Extent2D,
+        config: SwapchainConfig,
+        old_swapchain: Option<&Swapchain>,
+    ) -> Result<Self, RendererError> {
         let device = &render_device.device;
         let swapchian_extension = device
             .get_extension::<{ PhysicalDeviceExtensionType::KhrSwapchain }>()
-            .unwrap();
-        // TODO notice pipeline about the format
-        let surface_format = surface.get_physical_device_surface_formats()[0];
+            .ok_or(RendererError::UnsupportedSurface)?;
+        let surface_format = config.choose_surface_format(surface);
         let surface_capabilities = surface.get_physical_device_surface_capabilities();
         let mut desired_image_count = surface_capabilities.min_image_count + 1;
         if surface_capabilities.max_image_count > 0
@@ -31,10 +201,7 @@ impl ImageViewSwapchain {
         {
             desired_image_count = surface_capabilities.max_image_count;
         }
-        let surface_resolution = match surface_capabilities.current_extent.width {
-            u32::MAX => *resolution,
-            _ => surface_capabilities.current_extent,
-        };
+        let surface_resolution = Self::surface_extent(surface, resolution);
         let pre_transform = if surface_capabilities
             .supported_transforms
             .contains(SurfaceTransformFlagsKHR::IDENTITY)
@@ -43,26 +210,87 @@ impl ImageViewSwapchain {
         } else {
             surface_capabilities.current_transform
         };
-        let present_modes = surface.get_physical_device_surface_present_modes();
-        let present_mode = present_modes
-            .iter()
-            .cloned()
-            .find(|&mode| mode == PresentModeKHR::FIFO)
-            .unwrap();
-        let swapchain = Swapchain::builder(surface.clone(), swapchian_extension.clone())
-            .min_image_count(desired_image_count)
-            .image_color_space(surface_format.color_space)
-            .image_format(surface_format.format)
-            .image_extent(surface_resolution)
-            .image_sharing_mode(SharingMode::EXCLUSIVE)
-            .pre_transform(pre_transform)
-            .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(present_mode)
-            .clipped()
-            .image_array_layers(1)
-            .build()
-            .unwrap();
+        let capturable = surface_capabilities
+            .supported_usage_flags
+            .contains(ImageUsageFlags::TRANSFER_SRC);
+        let image_usage = if capturable {
+            ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_SRC
+        } else {
+            ImageUsageFlags::COLOR_ATTACHMENT
+        };
+        let present_mode = config.choose_present_mode(surface);
+        let mut swapchain_builder =
+            Swapchain::builder(surface.clone(), swapchian_extension.clone())
+                .min_image_count(desired_image_count)
+                .image_color_space(surface_format.color_space)
+                .image_format(surface_format.format)
+                .image_extent(surface_resolution)
+                .image_usage(image_usage)
+                .image_sharing_mode(SharingMode::EXCLUSIVE)
+                .pre_transform(pre_transform)
+                .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
+                .present_mode(present_mode)
+                .clipped()
+                .image_array_layers(1);
+        if let Some(old_swapchain) = old_swapchain {
+            swapchain_builder = swapchain_builder.old_swapchain(old_swapchain);
+        }
+        let swapchain = swapchain_builder.build()?;
+
+        Ok(Self {
+            swapchain,
+            format: surface_format.format,
+            color_space: surface_format.color_space,
+            present_mode,
+            capturable,
+            config,
+        })
+    }
+}
 
-        Self { swapchain }
+impl RenderTarget for ImageViewSwapchain {
+    fn format(&self) -> Format {
+        self.format
+    }
+    fn extent(&self) -> Extent2D {
+        self.swapchain.image_extent
+    }
+    fn final_layout(&self) -> ImageLayout {
+        ImageLayout::PRESENT_SRC_KHR
+    }
+    fn color_space(&self) -> ColorSpaceKHR {
+        self.color_space
+    }
+    fn hdr(&self) -> Option<&HdrConfig> {
+        self.config.hdr.as_ref()
+    }
+    fn create_color_image_views(&self) -> Vec<(ImageHandle, Arc<ImageView>)> {
+        self.swapchain
+            .get_swapchain_images()
+            .iter()
+            .map(|image| {
+                let image_view = ImageView::builder(image.clone())
+                    .view_type(ImageViewType::Type2d)
+                    .format(self.format)
+                    .components(ComponentMapping {
+                        r: ComponentSwizzle::R,
+                        g: ComponentSwizzle::G,
+                        b: ComponentSwizzle::B,
+                        a: ComponentSwizzle::A,
+                    })
+                    .subresource_range(
+                        ImageSubresourceRange::builder()
+                            .aspect_mask(ImageAspectFlags::COLOR)
+                            .base_mip_level(0)
+                            .level_count(1)
+                            .base_array_layer(0)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .build()
+                    .unwrap();
+                (image.handle(), image_view)
+            })
+            .collect()
     }
 }
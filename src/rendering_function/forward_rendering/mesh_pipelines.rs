@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+use yarvk::pipeline::{Pipeline, PipelineCacheType};
+use yarvk::render_pass::RenderPass;
+use yarvk::{Format, SampleCountFlags};
+
+use crate::pipeline::common_pipeline::{CommonPipeline, InstanceInput};
+use crate::render_device::RenderDevice;
+use crate::render_objects::camera::Camera;
+use crate::render_objects::mesh_renderer::{BlendMode, StencilState};
+
+/// Pipelines drawing meshes in render passes compatible with `render_pass`.
+pub(crate) struct MeshPipelines {
+    render_pass: Arc<RenderPass>,
+    color_format: Format,
+    sample_count: SampleCountFlags,
+    /// Variants for the instance input, stencil state and blend mode of the renderers, built
+    /// on first use except the default one.
+    pipelines: FxHashMap<(InstanceInput, Option<StencilState>, BlendMode), CommonPipeline>,
+}
+
+impl MeshPipelines {
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        render_pass: &Arc<RenderPass>,
+        color_format: Format,
+        sample_count: SampleCountFlags,
+    ) -> Self {
+        let mut mesh_pipelines = Self {
+            render_pass: render_pass.clone(),
+            color_format,
+            sample_count,
+            pipelines: Default::default(),
+        };
+        mesh_pipelines.prepare(render_device, InstanceInput::Model, None, BlendMode::Opaque);
+        mesh_pipelines
+    }
+    pub(crate) fn prepare_pipelines<'a>(
+        &mut self,
+        render_device: &RenderDevice,
+        cameras: impl Iterator<Item = &'a Camera>,
+    ) {
+        for camera in cameras {
+            for mesh_renderer in &camera.mesh_renderers {
+                self.prepare(
+                    render_device,
+                    InstanceInput::Model,
+                    mesh_renderer.stencil,
+                    mesh_renderer.blend_mode,
+                );
+            }
+            for instanced_mesh_renderer in &camera.instanced_mesh_renderers {
+                self.prepare(
+                    render_device,
+                    InstanceInput::InstanceData,
+                    instanced_mesh_renderer.stencil,
+                    instanced_mesh_renderer.blend_mode,
+                );
+            }
+        }
+    }
+    fn prepare(
+        &mut self,
+        render_device: &RenderDevice,
+        instance_input: InstanceInput,
+        stencil: Option<StencilState>,
+        blend_mode: BlendMode,
+    ) {
+        if self
+            .pipelines
+            .contains_key(&(instance_input, stencil, blend_mode))
+        {
+            return;
+        }
+        // the variant stays keyed by the requested state, the mesh is drawn without stencil
+        let pipeline_stencil = if stencil.is_some() && !render_device.depth_has_stencil() {
+            #[cfg(feature = "log")]
+            log::warn!("stencil state ignored, the depth image format has no stencil component");
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "stencil state ignored, the depth image format has no stencil component"
+            );
+            None
+        } else {
+            stencil
+        };
+        let pipeline = CommonPipeline::new(
+            &render_device.single_image_descriptor_set_layout,
+            PipelineCacheType::InternallySynchronized(&render_device.pipeline_cache),
+            &self.render_pass,
+            0,
+            self.color_format,
+            self.sample_count,
+            pipeline_stencil,
+            blend_mode,
+            instance_input,
+        );
+        self.pipelines
+            .insert((instance_input, stencil, blend_mode), pipeline);
+    }
+    pub(crate) fn get(
+        &self,
+        instance_input: InstanceInput,
+        stencil: Option<StencilState>,
+        blend_mode: BlendMode,
+    ) -> &Arc<Pipeline> {
+        &self
+            .pipelines
+            .get(&(instance_input, stencil, blend_mode))
+            .expect("internal error: mesh pipeline not prepared")
+            .pipeline
+    }
+}
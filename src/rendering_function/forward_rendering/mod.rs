@@ -5,11 +5,14 @@ use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator};
 use rustc_hash::FxHashMap;
 use tyleri_gpu_utils::memory::array_device_memory::ArrayDeviceMemory;
+use tyleri_gpu_utils::memory::variable_length_buffer::VariableLengthBuffer;
 use tyleri_gpu_utils::memory::{try_memory_type, IMemBakImg};
 use yarvk::command::command_buffer::Level::{PRIMARY, SECONDARY};
 use yarvk::command::command_buffer::RenderPassScope::OUTSIDE;
-use yarvk::command::command_buffer::State::{EXECUTABLE, INITIAL};
-use yarvk::command::command_buffer::{CommandBuffer, CommandBufferInheritanceInfo};
+use yarvk::command::command_buffer::State::{EXECUTABLE, INITIAL, RECORDING};
+use yarvk::command::command_buffer::{
+    CommandBuffer, CommandBufferInheritanceInfo, TransientCommandBuffer,
+};
 use yarvk::device_memory::IMemoryRequirements;
 use yarvk::frame_buffer::Framebuffer;
 use yarvk::image_subresource_range::ImageSubresourceRange;
@@ -23,54 +26,76 @@ use yarvk::render_pass::subpass::{SubpassDependency, SubpassDescription};
 use yarvk::render_pass::RenderPass;
 use yarvk::{
     AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ClearColorValue, ClearDepthStencilValue,
-    ClearValue, ComponentMapping, ComponentSwizzle, ContinuousImage, Extent2D, Format, Handle,
-    ImageAspectFlags, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, MemoryPropertyFlags,
-    SampleCountFlags, SubpassContents, SUBPASS_EXTERNAL,
+    ClearValue, ColorSpaceKHR, ContinuousImage, Extent2D, Format, ImageAspectFlags, ImageLayout,
+    ImageTiling, ImageType, ImageUsageFlags, MemoryPropertyFlags, SampleCountFlags,
+    SubpassContents, SUBPASS_EXTERNAL,
 };
 
-use crate::pipeline::common_pipeline::CommonPipeline;
 use crate::pipeline::ui_pipeline::UIPipeline;
 use crate::render_device::RenderDevice;
+use crate::render_objects::camera::Camera;
+use crate::render_objects::mesh_renderer::{MeshDraw, MeshInstance};
+use crate::render_objects::ParallelGroup;
 use crate::render_scene::RenderResources;
-use crate::render_window::swapchain::ImageViewSwapchain;
+use crate::render_target::hdr::HDR_INTERMEDIATE_FORMAT;
+use crate::render_target::render_texture::{RenderTexture, RENDER_TEXTURE_FORMAT};
+use crate::render_target::RenderTarget;
 use crate::render_window::ImageHandle;
+use crate::rendering_function::forward_rendering::mesh_pipelines::MeshPipelines;
+use crate::rendering_function::forward_rendering::tone_mapping::{ToneMapPass, ToneMapStore};
 use crate::rendering_function::RenderingFunction;
 
+mod mesh_pipelines;
 mod stages;
+mod tone_mapping;
+
+/// Attachment the multisampled color attachment is resolved into.
+const RESOLVE_ATTACHMENT_INDEX: u32 = 2;
 
 pub(crate) struct FrameStore {
     pub(crate) render_pass_begin_info: Arc<RenderPassBeginInfo>,
     pub(crate) inheritance_info: Arc<CommandBufferInheritanceInfo>,
+    pub(crate) color_image_view: Arc<ImageView>,
+    pub(crate) tone_map: Option<ToneMapStore>,
 }
 
 pub struct ForwardRenderingFunction {
     frame_stores: FxHashMap<u64 /*command buffer handler*/, FrameStore>,
-    common_pipeline: CommonPipeline,
+    render_pass: Arc<RenderPass>,
+    format: Format,
+    final_layout: ImageLayout,
+    color_space: ColorSpaceKHR,
+    scene_format: Format,
+    scene_pipelines: MeshPipelines,
+    /// Pipelines for cameras rendering into a `RenderTexture`, built on first use. Every
+    /// render texture has a compatible render pass.
+    texture_pipelines: Option<MeshPipelines>,
     ui_pipeline: UIPipeline,
+    tone_map_pass: Option<ToneMapPass>,
 }
 
 impl ForwardRenderingFunction {
-    fn create_depth_images(
+    /// Attachments only used within the render pass, multisampled with the device's sample count.
+    fn create_transient_images(
         render_device: &RenderDevice,
+        format: Format,
+        usage: ImageUsageFlags,
         surface_resolution: Extent2D,
         counts: usize,
     ) -> Option<Vec<Arc<IMemBakImg>>> {
         let device = &render_device.device;
-        let depth_image_format = render_device.depth_image_format;
-        let mut depth_image_builder = ContinuousImage::builder(device);
-        depth_image_builder.image_type(ImageType::TYPE_2D);
-        depth_image_builder.format(depth_image_format);
-        depth_image_builder.extent(surface_resolution.into());
-        depth_image_builder.mip_levels(1);
-        depth_image_builder.array_layers(1);
-        depth_image_builder.samples(SampleCountFlags::TYPE_1);
-        depth_image_builder.tiling(ImageTiling::OPTIMAL);
-        depth_image_builder.usage(
-            ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ImageUsageFlags::TRANSIENT_ATTACHMENT,
-        );
-        depth_image_builder.sharing_mode(SharingMode::EXCLUSIVE);
-        let depth_image = depth_image_builder.build().ok().unwrap();
-        let memory_requirement = depth_image.get_memory_requirements();
+        let mut image_builder = ContinuousImage::builder(device);
+        image_builder.image_type(ImageType::TYPE_2D);
+        image_builder.format(format);
+        image_builder.extent(surface_resolution.into());
+        image_builder.mip_levels(1);
+        image_builder.array_layers(1);
+        image_builder.samples(render_device.msaa_sample_count);
+        image_builder.tiling(ImageTiling::OPTIMAL);
+        image_builder.usage(usage | ImageUsageFlags::TRANSIENT_ATTACHMENT);
+        image_builder.sharing_mode(SharingMode::EXCLUSIVE);
+        let image = image_builder.build().ok().unwrap();
+        let memory_requirement = image.get_memory_requirements();
         let result = try_memory_type(
             memory_requirement,
             device.physical_device.memory_properties(),
@@ -79,7 +104,7 @@ impl ForwardRenderingFunction {
             |memory_type| {
                 return ArrayDeviceMemory::new_resources(
                     &device,
-                    &depth_image_builder,
+                    &image_builder,
                     counts,
                     &memory_type,
                 )
@@ -97,7 +122,7 @@ impl ForwardRenderingFunction {
                 |memory_type| {
                     return ArrayDeviceMemory::new_resources(
                         &device,
-                        &depth_image_builder,
+                        &image_builder,
                         counts,
                         &memory_type,
                     )
@@ -106,52 +131,93 @@ impl ForwardRenderingFunction {
             )
         }
     }
-}
-
-impl RenderingFunction for ForwardRenderingFunction {
-    fn new(render_device: &RenderDevice, swapchain: &ImageViewSwapchain) -> Self {
-        let device = &render_device.device;
-        let present_images = swapchain.swapchain.get_swapchain_images();
-        let surface_format = swapchain
-            .swapchain
-            .surface
-            .get_physical_device_surface_formats()[0];
-        let surface_resolution = swapchain.swapchain.image_extent;
-        let render_pass = RenderPass::builder(&device)
-            .add_attachment(
-                AttachmentDescription::builder()
-                    .format(surface_format.format)
-                    .samples(SampleCountFlags::TYPE_1)
-                    .load_op(AttachmentLoadOp::CLEAR)
-                    .store_op(AttachmentStoreOp::STORE)
-                    .final_layout(ImageLayout::PRESENT_SRC_KHR)
+    /// Format and final layout of the images the scene pass renders into, the intermediate
+    /// image when tone mapping.
+    fn scene_color_attachment(render_target: &dyn RenderTarget) -> (Format, ImageLayout) {
+        match render_target.hdr() {
+            Some(_) => (
+                HDR_INTERMEDIATE_FORMAT,
+                ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            None => (render_target.format(), render_target.final_layout()),
+        }
+    }
+    fn depth_attachment(render_device: &RenderDevice) -> AttachmentDescription {
+        let depth_attachment_builder = AttachmentDescription::builder()
+            .format(render_device.depth_image_format)
+            .samples(render_device.msaa_sample_count)
+            .load_op(AttachmentLoadOp::CLEAR)
+            .initial_layout(ImageLayout::UNDEFINED)
+            .final_layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        if render_device.depth_has_stencil() {
+            depth_attachment_builder
+                .stencil_load_op(AttachmentLoadOp::CLEAR)
+                .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+                .build()
+        } else {
+            depth_attachment_builder.build()
+        }
+    }
+    fn create_render_pass(
+        render_device: &RenderDevice,
+        render_target: &dyn RenderTarget,
+    ) -> Arc<RenderPass> {
+        let (color_format, final_layout) = Self::scene_color_attachment(render_target);
+        let sample_count = render_device.msaa_sample_count;
+        let multisampled = sample_count != SampleCountFlags::TYPE_1;
+        let color_attachment = if multisampled {
+            // only the resolved image outlives the render pass
+            AttachmentDescription::builder()
+                .format(color_format)
+                .samples(sample_count)
+                .load_op(AttachmentLoadOp::CLEAR)
+                .store_op(AttachmentStoreOp::DONT_CARE)
+                .final_layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .build()
+        } else {
+            AttachmentDescription::builder()
+                .format(color_format)
+                .samples(sample_count)
+                .load_op(AttachmentLoadOp::CLEAR)
+                .store_op(AttachmentStoreOp::STORE)
+                .final_layout(final_layout)
+                .build()
+        };
+        let mut subpass_builder = SubpassDescription::builder()
+            .add_color_attachment(
+                AttachmentReference::builder()
+                    .attachment_index(0)
+                    .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                     .build(),
             )
-            .add_attachment(
+            .depth_stencil_attachment(
+                AttachmentReference::builder()
+                    .attachment_index(1)
+                    .layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .build(),
+            );
+        let mut render_pass_builder = RenderPass::builder(&render_device.device)
+            .add_attachment(color_attachment)
+            .add_attachment(Self::depth_attachment(render_device));
+        if multisampled {
+            render_pass_builder = render_pass_builder.add_attachment(
                 AttachmentDescription::builder()
-                    .format(Format::D16_UNORM)
+                    .format(color_format)
                     .samples(SampleCountFlags::TYPE_1)
-                    .load_op(AttachmentLoadOp::CLEAR)
-                    .initial_layout(ImageLayout::UNDEFINED)
-                    .final_layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .load_op(AttachmentLoadOp::DONT_CARE)
+                    .store_op(AttachmentStoreOp::STORE)
+                    .final_layout(final_layout)
                     .build(),
-            )
-            .add_subpass(
-                SubpassDescription::builder()
-                    .add_color_attachment(
-                        AttachmentReference::builder()
-                            .attachment_index(0)
-                            .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                            .build(),
-                    )
-                    .depth_stencil_attachment(
-                        AttachmentReference::builder()
-                            .attachment_index(1)
-                            .layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-                            .build(),
-                    )
+            );
+            subpass_builder = subpass_builder.add_resolve_attachment(
+                AttachmentReference::builder()
+                    .attachment_index(RESOLVE_ATTACHMENT_INDEX)
+                    .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                     .build(),
-            )
+            );
+        }
+        render_pass_builder
+            .add_subpass(subpass_builder.build())
             .add_dependency(
                 SubpassDependency::builder()
                     .src_subpass(SUBPASS_EXTERNAL)
@@ -163,19 +229,173 @@ impl RenderingFunction for ForwardRenderingFunction {
                     .build(),
             )
             .build()
-            .unwrap();
-        let depth_images =
-            Self::create_depth_images(&render_device, surface_resolution, present_images.len())
-                .expect("no available memories for creating depth image");
-        let frame_stores = present_images
+            .unwrap()
+    }
+    fn prepare_pipelines(&mut self, render_device: &RenderDevice, cameras: &[Camera]) {
+        self.scene_pipelines.prepare_pipelines(
+            render_device,
+            cameras
+                .iter()
+                .filter(|camera| camera.render_texture().is_none()),
+        );
+        let render_texture = match cameras.iter().find_map(|camera| camera.render_texture()) {
+            Some(render_texture) => render_texture,
+            None => return,
+        };
+        self.texture_pipelines
+            .get_or_insert_with(|| {
+                MeshPipelines::new(
+                    render_device,
+                    &render_texture.render_pass,
+                    RENDER_TEXTURE_FORMAT,
+                    SampleCountFlags::TYPE_1,
+                )
+            })
+            .prepare_pipelines(
+                render_device,
+                cameras
+                    .iter()
+                    .filter(|camera| camera.render_texture().is_some()),
+            );
+    }
+    /// Renders the cameras of `render_texture` in one render pass before the scene pass, so
+    /// the texture is cleared once. Every camera records into its own `threads` secondary
+    /// command buffers.
+    fn record_render_texture(
+        &self,
+        render_device: &RenderDevice,
+        render_texture: &RenderTexture,
+        cameras: &[(&Camera, &ParallelGroup<MeshDraw>)],
+        mesh_instances: &Arc<VariableLengthBuffer<MeshInstance>>,
+        threads: usize,
+        secondary_command_buffers: Vec<CommandBuffer<{ SECONDARY }, { INITIAL }, { OUTSIDE }>>,
+        primary_command_buffer: CommandBuffer<{ PRIMARY }, { RECORDING }, { OUTSIDE }>,
+    ) -> CommandBuffer<{ PRIMARY }, { RECORDING }, { OUTSIDE }> {
+        let pipelines = self
+            .texture_pipelines
+            .as_ref()
+            .expect("internal error: render texture pipelines not prepared");
+        let mut primary_command_buffer = primary_command_buffer.cmd_begin_render_pass(
+            render_texture.render_pass_begin_info.clone(),
+            SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        );
+        let mut secondary_command_buffers: Vec<_> = secondary_command_buffers
+            .into_par_iter()
+            .map(|secondary_command_buffer| {
+                secondary_command_buffer
+                    .begin(render_texture.inheritance_info.clone())
+                    .unwrap()
+            })
+            .collect();
+        secondary_command_buffers
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, command_buffer)| {
+                let (camera, mesh_draws) = cameras[index / threads];
+                self.on_start(camera, command_buffer);
+                self.on_render_meshes(
+                    render_device,
+                    pipelines,
+                    camera,
+                    mesh_instances,
+                    mesh_draws,
+                    index % threads,
+                    command_buffer,
+                );
+            });
+        let secondary_command_buffers: Vec<_> = secondary_command_buffers
+            .into_par_iter()
+            .map(|secondary_command_buffer| secondary_command_buffer.end().unwrap())
+            .collect();
+        primary_command_buffer.cmd_execute_commands(secondary_command_buffers);
+        primary_command_buffer.cmd_end_render_pass()
+    }
+    /// Batches the visible meshes of every camera and writes their instances into the
+    /// instance buffer of the frame.
+    fn batch_meshes(render_details: &mut RenderResources) -> Vec<ParallelGroup<MeshDraw>> {
+        let mut camera_draws: Vec<_> = render_details
+            .cameras
+            .iter()
+            .map(|camera| camera.get_and_batch_meshes())
+            .collect();
+        let mesh_instances = Arc::get_mut(&mut render_details.mesh_instances)
+            .expect("internal error: instance buffer is holding by others");
+        let instances_len = camera_draws
+            .iter()
+            .flatten()
+            .map(|draw| match draw {
+                MeshDraw::Batch(batch) => batch.instances.len(),
+                MeshDraw::Instanced(_) => 0,
+            })
+            .sum();
+        mesh_instances.expand_to(instances_len);
+        for draw in camera_draws.iter_mut().flatten() {
+            if let MeshDraw::Batch(batch) = draw {
+                batch.first_instance = mesh_instances.write(&batch.instances) as u32;
+            }
+        }
+        camera_draws
+            .into_iter()
+            .map(ParallelGroup::from_ordered)
+            .collect()
+    }
+    fn create_frame_stores(
+        render_device: &RenderDevice,
+        render_pass: &Arc<RenderPass>,
+        tone_map_pass: Option<&ToneMapPass>,
+        render_target: &dyn RenderTarget,
+    ) -> FxHashMap<ImageHandle, FrameStore> {
+        let device = &render_device.device;
+        let color_image_views = render_target.create_color_image_views();
+        let surface_resolution = render_target.extent();
+        let depth_images = Self::create_transient_images(
+            &render_device,
+            render_device.depth_image_format,
+            ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            surface_resolution,
+            color_image_views.len(),
+        )
+        .expect("no available memories for creating depth image");
+        let (scene_format, _) = Self::scene_color_attachment(render_target);
+        let multisampled_images = (render_device.msaa_sample_count != SampleCountFlags::TYPE_1)
+            .then(|| {
+                Self::create_transient_images(
+                    &render_device,
+                    scene_format,
+                    ImageUsageFlags::COLOR_ATTACHMENT,
+                    surface_resolution,
+                    color_image_views.len(),
+                )
+                .expect("no available memories for creating multisampled color image")
+            });
+        // (scene attachment, tone mapping resources) of each frame
+        let scene_attachments: Vec<_> = match tone_map_pass {
+            Some(tone_map_pass) => {
+                let image_views: Vec<_> = color_image_views
+                    .iter()
+                    .map(|(_, image_view)| image_view.clone())
+                    .collect();
+                tone_map_pass
+                    .create_frame_stores(render_device, &image_views, surface_resolution)
+                    .into_iter()
+                    .map(|(hdr_image_view, tone_map_store)| (hdr_image_view, Some(tone_map_store)))
+                    .collect()
+            }
+            None => color_image_views
+                .iter()
+                .map(|(_, image_view)| (image_view.clone(), None))
+                .collect(),
+        };
+        color_image_views
             .par_iter()
             .enumerate()
-            .map(|(index, image)| {
+            .map(|(index, (image_handle, image_view))| {
+                let (scene_image_view, tone_map) = &scene_attachments[index];
                 // depth image
                 let depth_image_view = ImageView::builder(depth_images[index].clone())
                     .subresource_range(
                         ImageSubresourceRange::builder()
-                            .aspect_mask(ImageAspectFlags::DEPTH)
+                            .aspect_mask(render_device.depth_aspect_mask())
                             .level_count(1)
                             .layer_count(1)
                             .build(),
@@ -184,28 +404,29 @@ impl RenderingFunction for ForwardRenderingFunction {
                     .view_type(ImageViewType::Type2d)
                     .build()
                     .unwrap();
-                let image_view = ImageView::builder(image.clone())
-                    .view_type(ImageViewType::Type2d)
-                    .format(surface_format.format)
-                    .components(ComponentMapping {
-                        r: ComponentSwizzle::R,
-                        g: ComponentSwizzle::G,
-                        b: ComponentSwizzle::B,
-                        a: ComponentSwizzle::A,
-                    })
-                    .subresource_range(
-                        ImageSubresourceRange::builder()
-                            .aspect_mask(ImageAspectFlags::COLOR)
-                            .base_mip_level(0)
-                            .level_count(1)
-                            .base_array_layer(0)
-                            .layer_count(1)
-                            .build(),
-                    )
-                    .build()
-                    .unwrap();
-                let framebuffer = Framebuffer::builder(render_pass.clone())
-                    .add_attachment(0, image_view.clone())
+                let framebuffer_builder = match &multisampled_images {
+                    Some(multisampled_images) => {
+                        let multisampled_image_view =
+                            ImageView::builder(multisampled_images[index].clone())
+                                .subresource_range(
+                                    ImageSubresourceRange::builder()
+                                        .aspect_mask(ImageAspectFlags::COLOR)
+                                        .level_count(1)
+                                        .layer_count(1)
+                                        .build(),
+                                )
+                                .format(scene_format)
+                                .view_type(ImageViewType::Type2d)
+                                .build()
+                                .unwrap();
+                        Framebuffer::builder(render_pass.clone())
+                            .add_attachment(0, multisampled_image_view)
+                            .add_attachment(RESOLVE_ATTACHMENT_INDEX, scene_image_view.clone())
+                    }
+                    None => Framebuffer::builder(render_pass.clone())
+                        .add_attachment(0, scene_image_view.clone()),
+                };
+                let framebuffer = framebuffer_builder
                     .add_attachment(1, depth_image_view.clone())
                     .width(surface_resolution.width)
                     .height(surface_resolution.height)
@@ -223,7 +444,7 @@ impl RenderingFunction for ForwardRenderingFunction {
                         .add_clear_value(ClearValue {
                             depth_stencil: ClearDepthStencilValue {
                                 depth: 1.0,
-                                stencil: 0,
+                                stencil: render_device.stencil_clear_value,
                             },
                         })
                         .build(),
@@ -235,45 +456,155 @@ impl RenderingFunction for ForwardRenderingFunction {
                 let frame_store = FrameStore {
                     render_pass_begin_info,
                     inheritance_info,
+                    color_image_view: image_view.clone(),
+                    tone_map: tone_map.clone(),
                 };
-                Ok((image.handle(), frame_store))
+                Ok((*image_handle, frame_store))
             })
             .collect::<Result<FxHashMap<ImageHandle, FrameStore>, yarvk::Result>>()
-            .unwrap();
-        let common_pipeline = CommonPipeline::new(
-            &render_device.single_image_descriptor_set_layout,
-            PipelineCacheType::InternallySynchronized(&render_device.pipeline_cache),
+            .unwrap()
+    }
+}
+
+impl RenderingFunction for ForwardRenderingFunction {
+    fn new(render_device: &RenderDevice, render_target: &dyn RenderTarget) -> Self {
+        let render_pass = Self::create_render_pass(render_device, render_target);
+        let tone_map_pass = render_target
+            .hdr()
+            .map(|config| ToneMapPass::new(render_device, render_target, config));
+        let frame_stores = Self::create_frame_stores(
+            render_device,
             &render_pass,
-            0,
+            tone_map_pass.as_ref(),
+            render_target,
+        );
+        let (scene_format, _) = Self::scene_color_attachment(render_target);
+        let scene_pipelines = MeshPipelines::new(
+            render_device,
+            &render_pass,
+            scene_format,
+            render_device.msaa_sample_count,
         );
         let ui_pipeline = UIPipeline::new(
             &render_device.single_image_descriptor_set_layout,
             PipelineCacheType::InternallySynchronized(&render_device.pipeline_cache),
             &render_pass,
             0,
+            scene_format,
+            render_device.msaa_sample_count,
         );
         Self {
             frame_stores,
-            common_pipeline,
+            render_pass,
+            format: render_target.format(),
+            final_layout: render_target.final_layout(),
+            color_space: render_target.color_space(),
+            scene_format,
+            scene_pipelines,
+            texture_pipelines: None,
             ui_pipeline,
+            tone_map_pass,
         }
     }
 
+    fn on_swapchain_recreated(
+        &mut self,
+        render_device: &RenderDevice,
+        render_target: &dyn RenderTarget,
+    ) {
+        if render_target.format() != self.format
+            || render_target.final_layout() != self.final_layout
+            || render_target.color_space() != self.color_space
+            || render_target.hdr().is_some() != self.tone_map_pass.is_some()
+        {
+            // the render pass is not compatible anymore, pipelines have to be rebuilt
+            *self = Self::new(render_device, render_target);
+            return;
+        }
+        self.frame_stores = Self::create_frame_stores(
+            render_device,
+            &self.render_pass,
+            self.tone_map_pass.as_ref(),
+            render_target,
+        );
+    }
+
     fn record(
         &mut self,
         render_device: &RenderDevice,
         image_handle: &ImageHandle,
         primary_command_buffer: CommandBuffer<{ PRIMARY }, { INITIAL }, { OUTSIDE }>,
-        secondary_command_buffer: Vec<CommandBuffer<{ SECONDARY }, { INITIAL }, { OUTSIDE }>>,
-        render_details: &RenderResources,
+        mut secondary_command_buffer: Vec<CommandBuffer<{ SECONDARY }, { INITIAL }, { OUTSIDE }>>,
+        render_details: &mut RenderResources,
         scale_factor: f64,
         window_size: Extent2D,
     ) -> CommandBuffer<{ PRIMARY }, { EXECUTABLE }, { OUTSIDE }> {
+        self.prepare_pipelines(render_device, &render_details.cameras);
+        let camera_draws = Self::batch_meshes(render_details);
+        let render_details = &*render_details;
         let frame_store = self
             .frame_stores
             .get(image_handle)
             .expect("internal error: frame store not exist");
-        let primary_command_buffer = primary_command_buffer.begin().unwrap();
+        let cameras = render_details.cameras.as_slice();
+        // cameras sharing a render texture are recorded in one pass, in the order of their
+        // first camera
+        let mut texture_passes: Vec<(&Arc<RenderTexture>, Vec<_>)> = Vec::new();
+        let mut scene_cameras = Vec::new();
+        for (camera, mesh_draws) in cameras.iter().zip(&camera_draws) {
+            let render_texture = match camera.render_texture() {
+                Some(render_texture) => render_texture,
+                None => {
+                    scene_cameras.push((camera, mesh_draws));
+                    continue;
+                }
+            };
+            match texture_passes
+                .iter_mut()
+                .find(|(texture, _)| Arc::ptr_eq(texture, render_texture))
+            {
+                Some((_, texture_cameras)) => texture_cameras.push((camera, mesh_draws)),
+                None => texture_passes.push((render_texture, vec![(camera, mesh_draws)])),
+            }
+        }
+        // every camera records with one secondary command buffer per thread, executed in camera
+        // order; they return to the pool with the primary command buffer
+        let threads = rayon::current_num_threads();
+        let texture_camera_count: usize = texture_passes
+            .iter()
+            .map(|(_, texture_cameras)| texture_cameras.len())
+            .sum();
+        // the UI is recorded into the first buffer even without scene cameras
+        let scene_command_buffers = (threads * scene_cameras.len()).max(1);
+        let required_command_buffers = threads * texture_camera_count + scene_command_buffers;
+        // buffers left from frames with more cameras are freed instead of begun unused
+        secondary_command_buffer.truncate(required_command_buffers);
+        while secondary_command_buffer.len() < required_command_buffers {
+            secondary_command_buffer.push(
+                TransientCommandBuffer::<{ SECONDARY }>::new(
+                    &render_device.device,
+                    render_device.present_queue_family.clone(),
+                )
+                .unwrap(),
+            );
+        }
+        let mut texture_command_buffers = secondary_command_buffer.split_off(scene_command_buffers);
+        let mut primary_command_buffer = primary_command_buffer.begin().unwrap();
+        // render textures first, the scene pass samples them
+        for (render_texture, texture_cameras) in &texture_passes {
+            let command_buffers = texture_command_buffers
+                .drain(..threads * texture_cameras.len())
+                .collect();
+            primary_command_buffer = self.record_render_texture(
+                render_device,
+                render_texture,
+                texture_cameras,
+                &render_details.mesh_instances,
+                threads,
+                command_buffers,
+                primary_command_buffer,
+            );
+        }
         let mut primary_command_buffer = primary_command_buffer.cmd_begin_render_pass(
             frame_store.render_pass_begin_info.clone(),
             SubpassContents::SECONDARY_COMMAND_BUFFERS,
@@ -294,30 +625,45 @@ impl RenderingFunction for ForwardRenderingFunction {
             render_details,
             &mut secondary_command_buffers[0],
         );
-        let cameras = render_details.cameras.as_slice();
-        for camera in cameras {
-            let mesh_renderers = camera.get_and_order_meshes();
-            secondary_command_buffers
-                .par_iter_mut()
-                .enumerate()
-                .for_each(|(index, command_buffer)| {
-                    self.on_start(camera, command_buffer);
-                    self.on_render_meshes(
-                        render_device,
-                        camera,
-                        &mesh_renderers,
-                        index,
-                        command_buffer,
-                    );
-                });
-        }
+        secondary_command_buffers
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, command_buffer)| {
+                let (camera, mesh_draws) = match scene_cameras.get(index / threads) {
+                    Some(scene_camera) => scene_camera,
+                    None => return,
+                };
+                self.on_start(camera, command_buffer);
+                self.on_render_meshes(
+                    render_device,
+                    &self.scene_pipelines,
+                    camera,
+                    &render_details.mesh_instances,
+                    mesh_draws,
+                    index % threads,
+                    command_buffer,
+                );
+            });
 
         let secondary_command_buffer: Vec<_> = secondary_command_buffers
             .into_par_iter()
             .map(|secondary_command_buffer| secondary_command_buffer.end().unwrap())
             .collect();
         primary_command_buffer.cmd_execute_commands(secondary_command_buffer);
-        let primary_command_buffer = primary_command_buffer.cmd_end_render_pass();
+        let mut primary_command_buffer = primary_command_buffer.cmd_end_render_pass();
+        if let (Some(tone_map_pass), Some(tone_map_store)) =
+            (&self.tone_map_pass, &frame_store.tone_map)
+        {
+            primary_command_buffer =
+                tone_map_pass.record(tone_map_store, window_size, primary_command_buffer);
+        }
+        if let Some(frame_capture) = &render_details.frame_capture {
+            frame_capture.record(
+                &frame_store.color_image_view,
+                self.final_layout,
+                &mut primary_command_buffer,
+            );
+        }
         let primary_command_buffer = primary_command_buffer.end().unwrap();
         primary_command_buffer
     }
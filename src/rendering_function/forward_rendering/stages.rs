@@ -2,6 +2,7 @@ use std::slice::from_raw_parts;
 use std::sync::Arc;
 
 use glam::Vec2;
+use tyleri_gpu_utils::memory::variable_length_buffer::VariableLengthBuffer;
 use yarvk::command::command_buffer::CommandBuffer;
 use yarvk::command::command_buffer::Level::SECONDARY;
 use yarvk::command::command_buffer::RenderPassScope::INSIDE;
@@ -9,11 +10,13 @@ use yarvk::command::command_buffer::State::RECORDING;
 use yarvk::pipeline::shader_stage::ShaderStage;
 use yarvk::{Extent2D, IndexType, PipelineBindPoint, Rect2D, Viewport};
 
+use crate::pipeline::common_pipeline::InstanceInput;
 use crate::render_device::RenderDevice;
 use crate::render_objects::camera::Camera;
-use crate::render_objects::mesh_renderer::MeshRenderer;
+use crate::render_objects::mesh_renderer::{MeshDraw, MeshInstance, MeshRenderer};
 use crate::render_objects::ParallelGroup;
 use crate::render_scene::RenderResources;
+use crate::rendering_function::forward_rendering::mesh_pipelines::MeshPipelines;
 use crate::rendering_function::forward_rendering::ForwardRenderingFunction;
 
 impl ForwardRenderingFunction {
@@ -87,30 +90,29 @@ impl ForwardRenderingFunction {
     pub(super) fn on_render_meshes(
         &self,
         render_device: &RenderDevice,
+        pipelines: &MeshPipelines,
         camera: &Camera,
-        parallel_meshes: &ParallelGroup<Arc<MeshRenderer>>,
+        mesh_instances: &Arc<VariableLengthBuffer<MeshInstance>>,
+        parallel_draws: &ParallelGroup<MeshDraw>,
         thread_index: usize,
         command_buffer: &mut CommandBuffer<{ SECONDARY }, { RECORDING }, { INSIDE }>,
     ) {
-        let meshes = parallel_meshes
+        let draws = parallel_draws
             .get_group_by_thread(thread_index)
             .expect("internal error: no group in thread index");
-        if meshes.is_empty() {
+        if draws.is_empty() {
             return;
         }
-        let view_matrix = &camera.view_matrix;
-        let projection_matrix = &camera.get_projection_matrix();
-        command_buffer.cmd_bind_pipeline(
-            PipelineBindPoint::GRAPHICS,
-            self.common_pipeline.pipeline.clone(),
-        );
         command_buffer.cmd_bind_vertex_buffers(
             0,
-            [render_device
-                .memory_allocator
-                .static_vertices_buffer
-                .get_buffer() as _],
-            &[0],
+            [
+                render_device
+                    .memory_allocator
+                    .static_vertices_buffer
+                    .get_buffer() as _,
+                mesh_instances.clone() as _,
+            ],
+            &[0, 0],
         );
         command_buffer.cmd_bind_index_buffer(
             render_device
@@ -120,13 +122,58 @@ impl ForwardRenderingFunction {
             0,
             IndexType::UINT32,
         );
-        meshes.iter().for_each(|mesh_renderer| {
-            mesh_renderer.renderer_mesh(
-                &self.common_pipeline.pipeline,
-                view_matrix,
-                projection_matrix,
-                command_buffer,
-            );
-        })
+        let mut bound_variant = None;
+        // instanced mesh renderers bind their own instances
+        let mut mesh_instances_bound = true;
+        for draw in draws {
+            let variant = match draw {
+                MeshDraw::Batch(batch) => (
+                    InstanceInput::Model,
+                    batch.mesh_renderer.stencil,
+                    batch.mesh_renderer.blend_mode,
+                ),
+                MeshDraw::Instanced(instanced_mesh_renderer) => (
+                    InstanceInput::InstanceData,
+                    instanced_mesh_renderer.stencil,
+                    instanced_mesh_renderer.blend_mode,
+                ),
+            };
+            let pipeline = pipelines.get(variant.0, variant.1, variant.2);
+            if bound_variant != Some(variant) {
+                command_buffer.cmd_bind_pipeline(PipelineBindPoint::GRAPHICS, pipeline.clone());
+                // the variants have compatible layouts, the push constants stay valid
+                if bound_variant.is_none() {
+                    MeshRenderer::push_view_projection(
+                        pipeline,
+                        &camera.view_matrix,
+                        &camera.get_projection_matrix(),
+                        command_buffer,
+                    );
+                }
+                bound_variant = Some(variant);
+            }
+            match draw {
+                MeshDraw::Batch(batch) => {
+                    if !mesh_instances_bound {
+                        command_buffer.cmd_bind_vertex_buffers(
+                            1,
+                            [mesh_instances.clone() as _],
+                            &[0],
+                        );
+                        mesh_instances_bound = true;
+                    }
+                    batch.mesh_renderer.renderer_mesh(
+                        pipeline,
+                        batch.first_instance,
+                        batch.instances.len() as u32,
+                        command_buffer,
+                    );
+                }
+                MeshDraw::Instanced(instanced_mesh_renderer) => {
+                    instanced_mesh_renderer.renderer_instances(pipeline, command_buffer);
+                    mesh_instances_bound = false;
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,241 @@
+use std::slice::from_raw_parts;
+use std::sync::Arc;
+
+use tyleri_gpu_utils::memory::array_device_memory::ArrayDeviceMemory;
+use tyleri_gpu_utils::memory::{try_memory_type, IMemBakImg};
+use yarvk::command::command_buffer::CommandBuffer;
+use yarvk::command::command_buffer::Level::PRIMARY;
+use yarvk::command::command_buffer::RenderPassScope::OUTSIDE;
+use yarvk::command::command_buffer::State::RECORDING;
+use yarvk::device_memory::IMemoryRequirements;
+use yarvk::frame_buffer::Framebuffer;
+use yarvk::image_subresource_range::ImageSubresourceRange;
+use yarvk::image_view::{ImageView, ImageViewType};
+use yarvk::physical_device::SharingMode;
+use yarvk::pipeline::pipeline_stage_flags::PipelineStageFlag;
+use yarvk::pipeline::shader_stage::ShaderStage;
+use yarvk::pipeline::PipelineCacheType;
+use yarvk::render_pass::attachment::{AttachmentDescription, AttachmentReference};
+use yarvk::render_pass::render_pass_begin_info::RenderPassBeginInfo;
+use yarvk::render_pass::subpass::{SubpassDependency, SubpassDescription};
+use yarvk::render_pass::RenderPass;
+use yarvk::{
+    AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ContinuousImage, Extent2D, ImageAspectFlags,
+    ImageLayout, ImageTiling, ImageType, ImageUsageFlags, PipelineBindPoint, Rect2D,
+    SampleCountFlags, SubpassContents, Viewport, SUBPASS_EXTERNAL,
+};
+
+use crate::pipeline::tone_mapping_pipeline::{ToneMappingParams, ToneMappingPipeline};
+use crate::render_device::RenderDevice;
+use crate::render_target::hdr::{HdrConfig, ToneMapping, HDR_INTERMEDIATE_FORMAT};
+use crate::render_target::RenderTarget;
+use crate::resource::StaticTexture;
+
+/// Per frame resources of the tone mapping pass.
+#[derive(Clone)]
+pub(crate) struct ToneMapStore {
+    pub(crate) render_pass_begin_info: Arc<RenderPassBeginInfo>,
+    /// The intermediate image the scene is rendered into.
+    pub(crate) hdr_image: StaticTexture,
+}
+
+/// Samples the floating-point intermediate image and writes it into the render target,
+/// encoded for the target's color space.
+pub(crate) struct ToneMapPass {
+    render_pass: Arc<RenderPass>,
+    pipeline: ToneMappingPipeline,
+    params: ToneMappingParams,
+}
+
+impl ToneMapPass {
+    pub(crate) fn new(
+        render_device: &RenderDevice,
+        render_target: &dyn RenderTarget,
+        config: &HdrConfig,
+    ) -> Self {
+        let render_pass = Self::create_render_pass(render_device, render_target);
+        let pipeline = ToneMappingPipeline::new(
+            &render_device.single_image_descriptor_set_layout,
+            PipelineCacheType::InternallySynchronized(&render_device.pipeline_cache),
+            &render_pass,
+            0,
+            render_target.format(),
+            ToneMapping::for_color_space(render_target.color_space()),
+        );
+        Self {
+            render_pass,
+            pipeline,
+            params: ToneMappingParams {
+                exposure: config.exposure,
+                paper_white_nits: config.paper_white_nits,
+                max_luminance_nits: config.max_luminance_nits,
+            },
+        }
+    }
+    fn create_render_pass(
+        render_device: &RenderDevice,
+        render_target: &dyn RenderTarget,
+    ) -> Arc<RenderPass> {
+        RenderPass::builder(&render_device.device)
+            .add_attachment(
+                AttachmentDescription::builder()
+                    .format(render_target.format())
+                    .samples(SampleCountFlags::TYPE_1)
+                    // every pixel is overwritten by the full screen triangle
+                    .load_op(AttachmentLoadOp::DONT_CARE)
+                    .store_op(AttachmentStoreOp::STORE)
+                    .final_layout(render_target.final_layout())
+                    .build(),
+            )
+            .add_subpass(
+                SubpassDescription::builder()
+                    .add_color_attachment(
+                        AttachmentReference::builder()
+                            .attachment_index(0)
+                            .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .add_dependency(
+                SubpassDependency::builder()
+                    .src_subpass(SUBPASS_EXTERNAL)
+                    .add_src_stage_mask(PipelineStageFlag::ColorAttachmentOutput.into())
+                    .add_dst_stage_mask(PipelineStageFlag::FragmentShader.into())
+                    .add_dst_stage_mask(PipelineStageFlag::ColorAttachmentOutput.into())
+                    .src_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(AccessFlags::SHADER_READ | AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .build(),
+            )
+            .build()
+            .unwrap()
+    }
+    /// Creates the intermediate images for `color_image_views`, returns their views for the
+    /// scene pass and the tone mapping resources of each frame.
+    pub(crate) fn create_frame_stores(
+        &self,
+        render_device: &RenderDevice,
+        color_image_views: &[Arc<ImageView>],
+        extent: Extent2D,
+    ) -> Vec<(Arc<ImageView>, ToneMapStore)> {
+        let hdr_image_views: Vec<_> =
+            Self::create_hdr_images(render_device, extent, color_image_views.len())
+                .expect("no available memories for creating hdr intermediate image")
+                .into_iter()
+                .map(|image| {
+                    ImageView::builder(image)
+                        .view_type(ImageViewType::Type2d)
+                        .format(HDR_INTERMEDIATE_FORMAT)
+                        .subresource_range(
+                            ImageSubresourceRange::builder()
+                                .aspect_mask(ImageAspectFlags::COLOR)
+                                .level_count(1)
+                                .layer_count(1)
+                                .build(),
+                        )
+                        .build()
+                        .unwrap()
+                })
+                .collect();
+        let hdr_images = render_device.create_image_descriptor_sets(&hdr_image_views);
+        hdr_image_views
+            .into_iter()
+            .zip(hdr_images)
+            .zip(color_image_views)
+            .map(|((hdr_image_view, hdr_image), color_image_view)| {
+                let framebuffer = Framebuffer::builder(self.render_pass.clone())
+                    .add_attachment(0, color_image_view.clone())
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1)
+                    .build(&render_device.device)
+                    .unwrap();
+                let render_pass_begin_info = Arc::new(
+                    RenderPassBeginInfo::builder(self.render_pass.clone(), framebuffer)
+                        .render_area(extent.into())
+                        .build(),
+                );
+                let tone_map_store = ToneMapStore {
+                    render_pass_begin_info,
+                    hdr_image,
+                };
+                (hdr_image_view, tone_map_store)
+            })
+            .collect()
+    }
+    fn create_hdr_images(
+        render_device: &RenderDevice,
+        extent: Extent2D,
+        counts: usize,
+    ) -> Option<Vec<Arc<IMemBakImg>>> {
+        let device = &render_device.device;
+        let mut image_builder = ContinuousImage::builder(device);
+        image_builder.image_type(ImageType::TYPE_2D);
+        image_builder.format(HDR_INTERMEDIATE_FORMAT);
+        image_builder.extent(extent.into());
+        image_builder.mip_levels(1);
+        image_builder.array_layers(1);
+        image_builder.samples(SampleCountFlags::TYPE_1);
+        image_builder.tiling(ImageTiling::OPTIMAL);
+        image_builder.usage(ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::SAMPLED);
+        image_builder.sharing_mode(SharingMode::EXCLUSIVE);
+        let image = image_builder.build().ok()?;
+        let memory_requirement = image.get_memory_requirements();
+        try_memory_type(
+            memory_requirement,
+            device.physical_device.memory_properties(),
+            None,
+            memory_requirement.size * counts as u64,
+            |memory_type| {
+                ArrayDeviceMemory::new_resources(&device, &image_builder, counts, &memory_type).ok()
+            },
+        )
+    }
+    /// Records the pass inline, after the scene pass ended.
+    pub(crate) fn record(
+        &self,
+        tone_map_store: &ToneMapStore,
+        extent: Extent2D,
+        primary_command_buffer: CommandBuffer<{ PRIMARY }, { RECORDING }, { OUTSIDE }>,
+    ) -> CommandBuffer<{ PRIMARY }, { RECORDING }, { OUTSIDE }> {
+        let mut command_buffer = primary_command_buffer.cmd_begin_render_pass(
+            tone_map_store.render_pass_begin_info.clone(),
+            SubpassContents::INLINE,
+        );
+        let pipeline = &self.pipeline.pipeline;
+        command_buffer.cmd_bind_pipeline(PipelineBindPoint::GRAPHICS, pipeline.clone());
+        command_buffer.cmd_set_viewport(&Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as _,
+            height: extent.height as _,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        });
+        command_buffer.cmd_set_scissor(&Rect2D {
+            offset: Default::default(),
+            extent,
+        });
+        command_buffer.cmd_bind_descriptor_sets(
+            PipelineBindPoint::GRAPHICS,
+            pipeline.pipeline_layout.clone(),
+            0,
+            [tone_map_store.hdr_image.clone() as _],
+            &[],
+        );
+        let push_constant = unsafe {
+            from_raw_parts(
+                &self.params as *const ToneMappingParams as *const u8,
+                std::mem::size_of::<ToneMappingParams>(),
+            )
+        };
+        command_buffer.cmd_push_constants(
+            &pipeline.pipeline_layout.clone(),
+            &ShaderStage::Fragment,
+            0,
+            push_constant,
+        );
+        command_buffer.cmd_draw(3, 1, 0, 0);
+        command_buffer.cmd_end_render_pass()
+    }
+}
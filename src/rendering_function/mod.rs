@@ -6,20 +6,27 @@ use yarvk::Extent2D;
 
 use crate::render_device::RenderDevice;
 use crate::render_scene::RenderResources;
-use crate::render_window::swapchain::ImageViewSwapchain;
+use crate::render_target::RenderTarget;
 use crate::render_window::ImageHandle;
 
 pub mod forward_rendering;
 
 pub trait RenderingFunction {
-    fn new(render_device: &RenderDevice, swapchain: &ImageViewSwapchain) -> Self;
+    fn new(render_device: &RenderDevice, render_target: &dyn RenderTarget) -> Self;
+    /// Called after the swapchain was rebuilt, resources sized or keyed by the old images
+    /// have to be regenerated. The old images are not in use anymore.
+    fn on_swapchain_recreated(
+        &mut self,
+        render_device: &RenderDevice,
+        render_target: &dyn RenderTarget,
+    );
     fn record(
         &mut self,
         render_device: &RenderDevice,
         image_handle: &ImageHandle,
         primary_command_buffer: CommandBuffer<{ PRIMARY }, { INITIAL }, { OUTSIDE }>,
         secondary_command_buffer: Vec<CommandBuffer<{ SECONDARY }, { INITIAL }, { OUTSIDE }>>,
-        render_details: &RenderResources,
+        render_details: &mut RenderResources,
         scale_factor: f64,
         window_size: Extent2D,
     ) -> CommandBuffer<{ PRIMARY }, { EXECUTABLE }, { OUTSIDE }>;
@@ -1,3 +1,4 @@
+use std::ops::Deref;
 use std::sync::Arc;
 
 use tyleri_api::data_structure::vertices::Vertex;
@@ -19,14 +20,44 @@ use yarvk::{
 };
 
 use crate::render_device::RenderDevice;
+use crate::render_objects::bounding_volume::BoundingVolume;
+use crate::RendererError;
 
 pub mod resource_allocator;
 mod resource_info;
 
-pub type StaticVertices = Arc<BindlessBuffer<Vertex>>;
+/// Breaking change: was `Arc<BindlessBuffer<Vertex>>`, `MeshVertices` derefs to the buffer so
+/// `offset` and `len` are still reachable, code naming the buffer type has to go through
+/// `MeshVertices::buffer`.
+pub type StaticVertices = Arc<MeshVertices>;
 pub type StaticIndices = Arc<BindlessBuffer<u32>>;
 pub type StaticTexture = Arc<DescriptorSet<SingleImageDescriptorValue>>;
 
+/// Vertices in the static vertex buffer with the bounds of their positions, taken by
+/// `MeshRenderer::new`.
+pub struct MeshVertices {
+    buffer: Arc<BindlessBuffer<Vertex>>,
+    bounds: Option<BoundingVolume>,
+}
+
+impl MeshVertices {
+    pub fn buffer(&self) -> &Arc<BindlessBuffer<Vertex>> {
+        &self.buffer
+    }
+    /// None for an empty vertex list.
+    pub fn bounds(&self) -> Option<BoundingVolume> {
+        self.bounds
+    }
+}
+
+impl Deref for MeshVertices {
+    type Target = BindlessBuffer<Vertex>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
 impl RenderDevice {
     pub fn create_vertices(
         &self,
@@ -38,9 +69,31 @@ impl RenderDevice {
         if data.is_empty() {
             return Vec::new();
         }
+        // the bounds are taken from the written vertices before they are uploaded
+        let (data, bounds): (Vec<_>, Vec<_>) = data
+            .into_iter()
+            .map(|(len, f)| {
+                let mut vertices = vec![
+                    Vertex {
+                        pos: [0.0; 3],
+                        uv: [0.0; 2],
+                    };
+                    len
+                ];
+                f(&mut vertices);
+                let bounds = BoundingVolume::from_vertices(&vertices);
+                let f: Box<dyn FnOnce(&mut [Vertex]) + Send + Sync> =
+                    Box::new(move |buffer: &mut [Vertex]| buffer.copy_from_slice(&vertices));
+                ((len, f), bounds)
+            })
+            .unzip();
         self.memory_allocator
             .static_vertices_buffer
             .allocate(data, &mut self.memory_allocator.queue.lock())
+            .into_iter()
+            .zip(bounds)
+            .map(|(buffer, bounds)| Arc::new(MeshVertices { buffer, bounds }))
+            .collect()
     }
     pub fn create_indices(
         &self,
@@ -89,6 +142,7 @@ impl RenderDevice {
             .memory_type;
         let image_views: Vec<_> = self
             .create_image(builder, memory_type, data)
+            .unwrap()
             .into_iter()
             .map(|texture_image| {
                 ImageView::builder(texture_image.clone())
@@ -111,6 +165,14 @@ impl RenderDevice {
                     .unwrap()
             })
             .collect();
+        self.create_image_descriptor_sets(&image_views)
+    }
+    /// Wraps image views in `SHADER_READ_ONLY_OPTIMAL` layout into descriptor sets sampled
+    /// with the default sampler.
+    pub(crate) fn create_image_descriptor_sets(
+        &self,
+        image_views: &[Arc<ImageView>],
+    ) -> Vec<StaticTexture> {
         let mut descriptor_sets = Vec::with_capacity(image_views.len());
         self.single_image_descriptor_set_layout
             .descriptor_pool_list
@@ -134,23 +196,30 @@ impl RenderDevice {
             .map(|descriptor_set| Arc::new(descriptor_set))
             .collect()
     }
-    fn create_image(
+    /// Allocates images of the builder's format and uploads `data`, they are left in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout.
+    pub(crate) fn create_image(
         &self,
         mut builder: ContinuousImageBuilder,
         memory_type: &MemoryType,
         data: Vec<(Extent2D, Box<dyn FnOnce(&mut [u8]) + Send + Sync>)>,
-    ) -> Vec<Arc<IMemBakImg>> {
+    ) -> Result<Vec<Arc<IMemBakImg>>, RendererError> {
         let mut total_size = 0;
         for (extent, _) in data.as_slice() {
             total_size +=
                 extent.width as u64 * extent.height as u64 * builder.get_format().format_size();
         }
-        let it = data.iter().map(|(extent, _)| {
-            builder.extent(extent.clone().into());
-            builder.build().unwrap()
-        });
+        let images = data
+            .iter()
+            .map(|(extent, _)| {
+                builder.extent(extent.clone().into());
+                builder.build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let allocator = self.memory_allocator.get_block_based_allocator(memory_type);
-        let images = allocator.par_allocate(it, Some(total_size)).unwrap();
+        let images = allocator
+            .par_allocate(images.into_iter(), Some(total_size))
+            .map_err(|_| RendererError::Vulkan(yarvk::Result::ERROR_OUT_OF_DEVICE_MEMORY))?;
         let updater = MemoryUpdater::default();
         images
             .iter()
@@ -173,6 +242,6 @@ impl RenderDevice {
                 )
             });
         updater.update(&mut self.memory_allocator.queue.lock());
-        images
+        Ok(images)
     }
 }
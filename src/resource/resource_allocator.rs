@@ -1,14 +1,13 @@
 use std::sync::Arc;
 
-use parking_lot::Mutex;
 use tyleri_api::data_structure::vertices::Vertex;
 use tyleri_gpu_utils::memory::block_based_memory::bindless_buffer::BindlessBufferAllocator;
 use tyleri_gpu_utils::memory::block_based_memory::BlockBasedAllocator;
-use tyleri_gpu_utils::queue::parallel_recording_queue::ParallelRecordingQueue;
 use yarvk::device::Device;
 use yarvk::physical_device::memory_properties::MemoryType;
 use yarvk::Handle;
 
+use crate::render_device::SharedQueue;
 use crate::resource::resource_info::ResourcesInfo;
 use crate::FxDashMap;
 
@@ -17,7 +16,7 @@ const DEFAULT_INDICES_BUFFER_LEN: usize = 1024;
 
 pub struct MemoryAllocator {
     pub device: Arc<Device>,
-    pub(crate) queue: Mutex<ParallelRecordingQueue>,
+    pub(crate) queue: SharedQueue,
     block_based_allocators: FxDashMap<u64 /*memory type handler*/, Arc<BlockBasedAllocator>>,
     pub resource_infos: ResourcesInfo,
     pub static_vertices_buffer: Arc<BindlessBufferAllocator<Vertex>>,
@@ -25,7 +24,7 @@ pub struct MemoryAllocator {
 }
 
 impl MemoryAllocator {
-    pub fn new(device: &Arc<Device>, queue: ParallelRecordingQueue) -> Self {
+    pub fn new(device: &Arc<Device>, queue: SharedQueue) -> Self {
         let allocators = FxDashMap::default();
         let resource_infos = ResourcesInfo::new(device);
         let vertices_buffer = BindlessBufferAllocator::new(
@@ -44,7 +43,7 @@ impl MemoryAllocator {
         .unwrap();
         Self {
             device: device.clone(),
-            queue: Mutex::new(queue),
+            queue,
             block_based_allocators: allocators,
             resource_infos,
             static_vertices_buffer: vertices_buffer,
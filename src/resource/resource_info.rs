@@ -15,6 +15,7 @@ pub struct ResourcesInfo {
     pub static_indices_info: ResCreateInfo<ContinuousBufferBuilder>,
     pub ui_vertices_info: ResCreateInfo<ContinuousBufferBuilder>,
     pub ui_indices_info: ResCreateInfo<ContinuousBufferBuilder>,
+    pub instances_info: ResCreateInfo<ContinuousBufferBuilder>,
     pub texture_info: ResCreateInfo<ContinuousImageBuilder>,
 }
 
@@ -25,6 +26,7 @@ impl ResourcesInfo {
             static_indices_info: Self::create_indices_info(device, false),
             ui_vertices_info: Self::create_vertices_info(device, true),
             ui_indices_info: Self::create_indices_info(device, true),
+            instances_info: Self::create_vertices_info(device, true),
             texture_info: Self::create_texture_info(device),
         }
     }
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use tyleri_renderer::render_device::builders::RenderDeviceBuilder;
+use tyleri_renderer::render_device::RenderDevice;
+use tyleri_renderer::render_target::frame_capture::CapturedFrame;
+use tyleri_renderer::RendererError;
+
+/// Maximum difference allowed per channel, software rasterizers are not bit exact.
+pub const CHANNEL_TOLERANCE: u8 = 2;
+/// Set to regenerate the reference images from the current output.
+const BLESS_ENV: &str = "TYLERI_BLESS_GOLDEN";
+
+/// `None` if the machine has no vulkan driver, the test is skipped then instead of failing
+/// like a broken render.
+pub fn headless_render_device() -> Option<RenderDevice> {
+    let result = RenderDeviceBuilder::default()
+        .application_name("Tyleri Golden Tests")
+        .headless()
+        .build();
+    match result {
+        Ok(render_device) => Some(render_device),
+        Err(
+            e @ (RendererError::Loader(_)
+            | RendererError::Instance(yarvk::Result::ERROR_INCOMPATIBLE_DRIVER)),
+        ) => {
+            eprintln!("skipped, no vulkan driver installed: {e}");
+            None
+        }
+        Err(RendererError::NoSuitableDevice { rejected }) if rejected.is_empty() => {
+            eprintln!("skipped, no vulkan device found");
+            None
+        }
+        Err(e) => panic!("cannot create a headless render device: {e}"),
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_path(name: &str, suffix: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden-output");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(format!("{name}.{suffix}.png"))
+}
+
+fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "reference image {} must be RGBA8",
+        path.display()
+    );
+    pixels.truncate(info.buffer_size());
+    (info.width, info.height, pixels)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+}
+
+/// Compares `frame` with `tests/golden/<name>.png`, a missing reference fails until it is
+/// created with `TYLERI_BLESS_GOLDEN=1`. On mismatch the actual frame and a diff image,
+/// mismatched pixels in red, are written to `target/golden-output`.
+pub fn assert_golden(name: &str, frame: &CapturedFrame) {
+    let (width, height) = (frame.extent.width, frame.extent.height);
+    let golden = golden_path(name);
+    if std::env::var_os(BLESS_ENV).is_some() {
+        write_png(&golden, width, height, &frame.pixels);
+        return;
+    }
+    if !golden.exists() {
+        let actual_path = output_path(name, "actual");
+        write_png(&actual_path, width, height, &frame.pixels);
+        panic!(
+            "{name}: no reference image {}, review {} and run with {BLESS_ENV}=1 to create it",
+            golden.display(),
+            actual_path.display()
+        );
+    }
+    let (golden_width, golden_height, golden_pixels) = read_png(&golden);
+    assert_eq!(
+        (golden_width, golden_height),
+        (width, height),
+        "{name}: frame size differs from the reference image"
+    );
+    let mut diff = Vec::with_capacity(frame.pixels.len());
+    let mut mismatched = 0;
+    for (actual, expected) in frame
+        .pixels
+        .chunks_exact(4)
+        .zip(golden_pixels.chunks_exact(4))
+    {
+        let matched = actual
+            .iter()
+            .zip(expected)
+            .all(|(a, e)| a.abs_diff(*e) <= CHANNEL_TOLERANCE);
+        if matched {
+            // dimmed reference, so the mismatches stand out
+            diff.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+        } else {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        }
+    }
+    if mismatched > 0 {
+        let actual_path = output_path(name, "actual");
+        let diff_path = output_path(name, "diff");
+        write_png(&actual_path, width, height, &frame.pixels);
+        write_png(&diff_path, width, height, &diff);
+        panic!(
+            "{name}: {mismatched} pixels differ by more than {CHANNEL_TOLERANCE}, see {} and {}",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
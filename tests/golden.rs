@@ -0,0 +1,150 @@
+mod common;
+
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use tyleri_api::data_structure::vertices::{UIVertex, Vertex};
+use tyleri_renderer::render_device::RenderDevice;
+use tyleri_renderer::render_objects::camera::Camera;
+use tyleri_renderer::render_objects::mesh_renderer::MeshRenderer;
+use tyleri_renderer::render_target::offscreen_target::OffscreenTarget;
+use tyleri_renderer::resource::StaticTexture;
+use tyleri_renderer::ForwardRenderingFunction;
+use yarvk::{Extent2D, Rect2D, Viewport};
+
+use common::{assert_golden, headless_render_device};
+
+const RESOLUTION: Extent2D = Extent2D {
+    width: 64,
+    height: 64,
+};
+
+fn checker_texture(render_device: &RenderDevice) -> StaticTexture {
+    render_device
+        .create_textures(vec![(
+            Extent2D {
+                width: 2,
+                height: 2,
+            },
+            Box::new(|data: &mut [u8]| {
+                // red, green / blue, white
+                data.copy_from_slice(&[
+                    255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255,
+                ])
+            }),
+        )])
+        .pop()
+        .unwrap()
+}
+
+fn full_viewport_camera() -> Camera {
+    let mut camera = Camera::new();
+    camera.view_matrix = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 3.0), Vec3::ZERO, Vec3::Y);
+    camera.viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: RESOLUTION.width as _,
+        height: RESOLUTION.height as _,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    };
+    camera.scissor = Rect2D {
+        offset: Default::default(),
+        extent: RESOLUTION,
+    };
+    camera
+}
+
+#[test]
+fn textured_quad() {
+    let render_device = match headless_render_device() {
+        Some(render_device) => render_device,
+        None => return,
+    };
+    let mut target =
+        OffscreenTarget::<ForwardRenderingFunction>::new(&render_device, &RESOLUTION).unwrap();
+    let vertices = render_device
+        .create_vertices(vec![(
+            4,
+            Box::new(|vertices: &mut [Vertex]| {
+                vertices.copy_from_slice(&[
+                    Vertex {
+                        pos: [-1.0, -1.0, 0.0],
+                        uv: [0.0, 0.0],
+                    },
+                    Vertex {
+                        pos: [1.0, -1.0, 0.0],
+                        uv: [1.0, 0.0],
+                    },
+                    Vertex {
+                        pos: [1.0, 1.0, 0.0],
+                        uv: [1.0, 1.0],
+                    },
+                    Vertex {
+                        pos: [-1.0, 1.0, 0.0],
+                        uv: [0.0, 1.0],
+                    },
+                ])
+            }),
+        )])
+        .pop()
+        .unwrap();
+    let indices = render_device
+        .create_indices(vec![(
+            6,
+            Box::new(|indices: &mut [u32]| indices.copy_from_slice(&[0, 1, 2, 2, 3, 0])),
+        )])
+        .pop()
+        .unwrap();
+    let mut mesh_renderer = MeshRenderer::new(vertices, indices, checker_texture(&render_device));
+    mesh_renderer.model = Mat4::from_rotation_z(30f32.to_radians());
+    let mut camera = full_viewport_camera();
+    camera.mesh_renderers.push(Arc::new(mesh_renderer));
+    target.get_render_scene().add_camera(camera);
+
+    let receiver = target.capture_frame(&render_device).unwrap();
+    target.render(&render_device).unwrap();
+    assert_golden("textured_quad", &receiver.recv().unwrap());
+}
+
+#[test]
+fn ui_quad() {
+    let render_device = match headless_render_device() {
+        Some(render_device) => render_device,
+        None => return,
+    };
+    let mut target =
+        OffscreenTarget::<ForwardRenderingFunction>::new(&render_device, &RESOLUTION).unwrap();
+    let color = [1.0, 1.0, 1.0, 0.5];
+    let ui_vertices = vec![
+        UIVertex {
+            pos: [8.0, 8.0],
+            uv: [0.0, 0.0],
+            color,
+        },
+        UIVertex {
+            pos: [56.0, 8.0],
+            uv: [1.0, 0.0],
+            color,
+        },
+        UIVertex {
+            pos: [56.0, 40.0],
+            uv: [1.0, 1.0],
+            color,
+        },
+        UIVertex {
+            pos: [8.0, 40.0],
+            uv: [0.0, 1.0],
+            color,
+        },
+    ];
+    target.get_render_scene().add_ui(vec![(
+        ui_vertices,
+        vec![0, 1, 2, 2, 3, 0],
+        checker_texture(&render_device),
+    )]);
+
+    let receiver = target.capture_frame(&render_device).unwrap();
+    target.render(&render_device).unwrap();
+    assert_golden("ui_quad", &receiver.recv().unwrap());
+}